@@ -0,0 +1,734 @@
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use reqwest::header::{HeaderName, CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_RANGE, LAST_MODIFIED, RANGE};
+use reqwest::{RequestBuilder, StatusCode};
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+
+use crate::app_config::AppConfig;
+use crate::cache::{CachedResponse, ResponseCache};
+use crate::connectivity::Connectivity;
+use crate::error::SpinexusError;
+use crate::memo::RequestMemo;
+use crate::models::*;
+use crate::retry::{self, RateLimit, RateLimiter, RetryPolicy};
+
+pub const DEFAULT_API_URL: &str = "https://spinsha.re/api";
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+const USER_AGENT: &str = concat!("spinexus/", env!("CARGO_PKG_VERSION"));
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// API versions whose responses this client knows how to read.
+const KNOWN_API_VERSIONS: &[i32] = &[1];
+
+static WARNED_UNKNOWN_VERSION: AtomicBool = AtomicBool::new(false);
+
+/// How long cached responses are used without asking SpinShare whether they changed.
+mod ttl {
+    use std::time::Duration;
+
+    pub const FRONT_PAGE: Duration = Duration::from_secs(5 * 60);
+    pub const LISTING: Duration = Duration::from_secs(5 * 60);
+    pub const SEARCH: Duration = Duration::from_secs(5 * 60);
+    pub const CHART: Duration = Duration::from_secs(60 * 60);
+    pub const USER: Duration = Duration::from_secs(60 * 60);
+    pub const PLAYLIST: Duration = Duration::from_secs(30 * 60);
+    pub const REVIEWS: Duration = Duration::from_secs(10 * 60);
+}
+
+/// Envelope wrapped around every SpinShare response. `data` is only deserialized into
+/// the expected type once `status` has been checked, since error responses carry
+/// something else in it.
+#[derive(Serialize, Deserialize)]
+struct SpinRequest {
+    version: i32,
+    status: i32,
+    data: serde_json::Value,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SearchChartBody {
+    pub search_query: String,
+    #[serde(flatten)]
+    pub filters: SearchFilters,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SearchBody {
+    pub search_query: String,
+}
+
+/// Client for the SpinShare API.
+///
+/// Cloning is cheap: every clone shares the same connection pool.
+#[derive(Clone, Debug)]
+pub struct SpinShareClient {
+    http: reqwest::Client,
+    base_url: String,
+    /// Total timeout of API calls. File downloads can take any time they need.
+    timeout: Duration,
+    cache: Option<Arc<ResponseCache>>,
+    memo: Arc<RequestMemo>,
+    retry: RetryPolicy,
+    limiter: Arc<RateLimiter>,
+    connectivity: Arc<Connectivity>,
+    /// Skip fresh cache entries and always ask SpinShare.
+    force_refresh: bool,
+}
+
+impl SpinShareClient {
+    pub fn new() -> Self {
+        Self::builder().build().expect("Failed to build default SpinShare client")
+    }
+
+    pub fn builder() -> SpinShareClientBuilder {
+        SpinShareClientBuilder::default()
+    }
+
+    /// Builds a client from the API settings stored in the app config, falling back to
+    /// the default settings if the configured ones are invalid.
+    pub fn from_config(config: &AppConfig) -> Self {
+        Self::builder()
+            .base_url(config.api_url.clone())
+            .timeout(Duration::from_secs(config.api_timeout_secs))
+            .cache(ResponseCache::in_cache_dir())
+            .retry_policy(config.retry.clone())
+            .rate_limit(config.rate_limit.clone())
+            .build()
+            .unwrap_or_else(|_| Self::new())
+    }
+
+    /// Returns a client sharing this one's pool and cache that bypasses fresh cache
+    /// entries, for when the user explicitly asks for up-to-date data.
+    pub fn refreshing(&self) -> Self {
+        Self {
+            force_refresh: true,
+            ..self.clone()
+        }
+    }
+
+    pub fn clear_cache(&self) -> io::Result<()> {
+        self.memo.clear();
+        match &self.cache {
+            Some(cache) => cache.clear(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn connectivity(&self) -> &Connectivity {
+        &self.connectivity
+    }
+
+    /// Asks SpinShare whether it is reachable and updates the connectivity status.
+    /// Any response counts, even an error status.
+    pub async fn check_connection(&self) -> bool {
+        let online = self.http.get(self.endpoint("ping")).timeout(self.timeout).send().await.is_ok();
+        self.set_online(online);
+        online
+    }
+
+    fn set_online(&self, online: bool) {
+        // Responses memoized while offline came from the disk cache, drop them so the
+        // next request picks up fresh data.
+        if self.connectivity.set_online(online) && online {
+            self.memo.clear();
+        }
+    }
+
+    fn endpoint(&self, path: &str) -> String {
+        format!("{}/{}", self.base_url, path)
+    }
+
+    async fn request_data<T: DeserializeOwned>(&self, path: String, ttl: Duration) -> Result<T, SpinexusError> {
+        let url = self.endpoint(&path);
+        let key = ResponseCache::key(&url, None);
+        let body = self.fetch_shared(self.http.get(&url).timeout(self.timeout), key, url, ttl).await?;
+        parse_response(&body)
+    }
+
+    async fn post_data<T: DeserializeOwned, B: Serialize>(&self, path: &str, body: &B, ttl: Duration) -> Result<T, SpinexusError> {
+        let url = self.endpoint(path);
+        let body = serde_json::to_vec(body)?;
+        let key = ResponseCache::key(&url, Some(&body));
+        let request = self.http.post(&url)
+            .timeout(self.timeout)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body);
+        let body = self.fetch_shared(request, key, url, ttl).await?;
+        parse_response(&body)
+    }
+
+    /// Runs `fetch` through the in-memory layer, so that identical requests share one
+    /// trip to the disk cache or the network.
+    async fn fetch_shared(&self, request: RequestBuilder, key: String, url: String, ttl: Duration) -> Result<Arc<Vec<u8>>, SpinexusError> {
+        let client = self.clone();
+        let fetch_key = key.clone();
+        let fetch = async move { client.fetch(request, &fetch_key, &url, ttl).await };
+        self.memo.get_or_fetch(key, ttl, self.force_refresh, fetch).await
+    }
+
+    /// Sends a request through the response cache and returns the raw response body.
+    ///
+    /// Fresh entries are returned without touching the network. Stale ones are
+    /// revalidated with their ETag or Last-Modified date when SpinShare sent one, or
+    /// returned as is while SpinShare can't be reached.
+    async fn fetch(&self, request: RequestBuilder, key: &str, url: &str, ttl: Duration) -> Result<Vec<u8>, SpinexusError> {
+        let cache = match &self.cache {
+            Some(cache) => cache,
+            None => return Ok(self.send(request).await?.error_for_status()?.bytes().await?.to_vec()),
+        };
+
+        let cached = cache.get(key);
+        let mut request = request;
+        if let Some(cached) = &cached {
+            if !self.force_refresh && (cached.is_fresh(ttl) || !self.connectivity.is_online()) {
+                return Ok(cached.body.clone());
+            }
+            if let Some(etag) = &cached.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &cached.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+
+        let res = match (self.send(request).await, cached.as_ref()) {
+            (Err(SpinexusError::Network(err)), Some(cached)) if is_offline_error(&err) => return Ok(cached.body.clone()),
+            (res, _) => res?,
+        };
+        if res.status() == StatusCode::NOT_MODIFIED {
+            if let Some(cached) = cached {
+                let cached = CachedResponse::new(cached.body, cached.etag, cached.last_modified);
+                // A cache that can't be written to only costs us a refetch later.
+                let _ = cache.put(key, url, &cached);
+                return Ok(cached.body);
+            }
+        }
+
+        let res = res.error_for_status()?;
+        let etag = header_value(&res, ETAG);
+        let last_modified = header_value(&res, LAST_MODIFIED);
+        let body = res.bytes().await?.to_vec();
+        if is_success_envelope(&body) {
+            let _ = cache.put(key, url, &CachedResponse::new(body.clone(), etag, last_modified));
+        }
+        Ok(body)
+    }
+
+    /// Sends a request once the rate limiter allows it, and records whether SpinShare
+    /// could be reached.
+    ///
+    /// Idempotent requests are retried on timeouts, connection failures, server errors
+    /// and rate limiting, as configured by the retry policy. The last response is
+    /// returned as is, so error statuses still need to be checked.
+    async fn send(&self, request: RequestBuilder) -> Result<reqwest::Response, SpinexusError> {
+        let res = self.send_with_retries(request).await;
+        match &res {
+            Ok(_) => self.set_online(true),
+            Err(SpinexusError::Network(err)) if is_offline_error(err) => self.set_online(false),
+            Err(_) => {}
+        }
+        res
+    }
+
+    async fn send_with_retries(&self, request: RequestBuilder) -> Result<reqwest::Response, SpinexusError> {
+        let request = request.build()?;
+        let idempotent = request.method().is_idempotent();
+        let mut attempt = 0;
+        loop {
+            let this_try = match request.try_clone() {
+                Some(this_try) if idempotent => this_try,
+                _ => {
+                    self.limiter.acquire().await;
+                    return Ok(self.http.execute(request).await?);
+                }
+            };
+            self.limiter.acquire().await;
+            let res = self.http.execute(this_try).await;
+            let retry_after = match &res {
+                Ok(res) if retry::is_retryable_status(res.status()) => retry::retry_after(res),
+                Err(err) if is_offline_error(err) => None,
+                _ => return Ok(res?),
+            };
+            match self.retry.delay_for(attempt, retry_after) {
+                Some(delay) => tokio::time::sleep(delay).await,
+                None => return Ok(res?),
+            }
+            attempt += 1;
+        }
+    }
+
+    /// Starts downloading a file, e.g. a chart zip, with the same retry and rate limiting
+    /// as API calls. Unlike them, downloads have no total timeout, as large charts can
+    /// take a while on slow connections.
+    pub async fn get_file(&self, url: &str) -> Result<reqwest::Response, SpinexusError> {
+        Ok(self.send(self.http.get(url)).await?.error_for_status()?)
+    }
+
+    /// Asks for the rest of a file from byte `start` on, provided it still has the given
+    /// ETag. The status is left unchecked so that callers can tell a resumed download
+    /// (206) from a server sending the whole file again (200) or having nothing left
+    /// to send (416).
+    pub async fn get_file_range(&self, url: &str, start: u64, etag: &str) -> Result<reqwest::Response, SpinexusError> {
+        let request = self.http.get(url)
+            .header(RANGE, format!("bytes={start}-"))
+            .header(IF_RANGE, etag);
+        self.send(request).await
+    }
+
+    pub async fn get_front_page(&self) -> Result<FrontPage, SpinexusError> {
+        self.request_data("frontPage".into(), ttl::FRONT_PAGE).await
+    }
+
+    pub async fn get_chart(&self, id: i32) -> Result<FullChart, SpinexusError> {
+        self.request_data(format!("song/{}", id), ttl::CHART).await
+    }
+
+    /// Looks a chart up by its file reference, the name of its `.srtb` file. The
+    /// extension may be left out.
+    pub async fn get_chart_by_reference(&self, reference: &str) -> Result<FullChart, SpinexusError> {
+        let reference = reference.strip_suffix(".srtb").unwrap_or(reference);
+        self.request_data(format!("song/{}", path_segment(reference)), ttl::CHART).await
+    }
+
+    pub async fn get_reviews(&self, chart_id: i32) -> Result<Vec<Review>, SpinexusError> {
+        self.request_data(format!("song/{}/reviews", chart_id), ttl::REVIEWS).await
+    }
+
+    pub async fn get_spinplays(&self, chart_id: i32) -> Result<Vec<SpinPlay>, SpinexusError> {
+        self.request_data(format!("song/{}/spinplays", chart_id), ttl::REVIEWS).await
+    }
+
+    pub async fn get_chart_list(&self, list: ChartList, page: i32) -> Result<Vec<PartialChart>, SpinexusError> {
+        self.request_data(format!("{}/{}", list.path(), page), ttl::LISTING).await
+    }
+
+    /// Fetches a page of a chart listing and works out whether it is the last one.
+    ///
+    /// SpinShare doesn't say how many pages there are: a short page is the last one, and
+    /// after a full page the next one is fetched to check that it isn't empty. That
+    /// request goes through the cache, so it costs nothing once the user moves on.
+    pub async fn get_chart_page(&self, list: ChartList, number: i32) -> Result<Page<PartialChart>, SpinexusError> {
+        let items = self.get_chart_list(list, number).await?;
+        let is_last = items.len() < PAGE_SIZE
+            || self.get_chart_list(list, number + 1).await.is_ok_and(|next| next.is_empty());
+        Ok(Page {
+            number,
+            items,
+            is_last,
+            page_count: is_last.then_some(number + 1),
+        })
+    }
+
+    pub async fn get_user(&self, id: i32) -> Result<User, SpinexusError> {
+        self.request_data(format!("user/{}", id), ttl::USER).await
+    }
+
+    pub async fn get_charts_for_user(&self, id: i32) -> Result<Vec<PartialChart>, SpinexusError> {
+        self.request_data(format!("user/{}/charts", id), ttl::LISTING).await
+    }
+
+    pub async fn get_playlist(&self, id: i32) -> Result<Playlist, SpinexusError> {
+        self.request_data(format!("playlist/{}", id), ttl::PLAYLIST).await
+    }
+
+    pub async fn get_playlists_for_user(&self, id: i32) -> Result<Vec<PartialPlaylist>, SpinexusError> {
+        self.request_data(format!("user/{}/playlists", id), ttl::LISTING).await
+    }
+
+    pub async fn search_chart(&self, query: String, filters: SearchFilters) -> Result<Vec<PartialChart>, SpinexusError> {
+        let body = SearchChartBody {
+            search_query: query,
+            filters,
+        };
+        self.post_data("searchCharts", &body, ttl::SEARCH).await
+    }
+
+    /// Searches users only. The search page takes users from `search_all` instead, which
+    /// answers both of its tabs at once.
+    #[allow(dead_code)]
+    pub async fn search_users(&self, query: String) -> Result<Vec<User>, SpinexusError> {
+        self.post_data("searchUsers", &SearchBody { search_query: query }, ttl::SEARCH).await
+    }
+
+    /// Searches charts and users at once. Charts are not filtered.
+    pub async fn search_all(&self, query: String) -> Result<SearchResults, SpinexusError> {
+        self.post_data("searchAll", &SearchBody { search_query: query }, ttl::SEARCH).await
+    }
+}
+
+impl Default for SpinShareClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PartialEq for SpinShareClient {
+    fn eq(&self, other: &Self) -> bool {
+        self.base_url == other.base_url && self.force_refresh == other.force_refresh
+    }
+}
+
+pub struct SpinShareClientBuilder {
+    base_url: String,
+    timeout: Duration,
+    cache: Option<ResponseCache>,
+    retry: RetryPolicy,
+    rate_limit: RateLimit,
+}
+
+impl Default for SpinShareClientBuilder {
+    fn default() -> Self {
+        Self {
+            base_url: DEFAULT_API_URL.into(),
+            timeout: DEFAULT_TIMEOUT,
+            cache: None,
+            retry: RetryPolicy::default(),
+            rate_limit: RateLimit::default(),
+        }
+    }
+}
+
+impl SpinShareClientBuilder {
+    /// Sets the API root, e.g. `https://spinsha.re/api` or `http://localhost:8080/api`.
+    pub fn base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    /// Sets the total timeout for a single API call, from connecting until the body is
+    /// read. File downloads only use it to limit connecting.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Caches responses on disk. Without a cache every call goes to the network.
+    pub fn cache(mut self, cache: ResponseCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    pub fn retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn rate_limit(mut self, rate_limit: RateLimit) -> Self {
+        self.rate_limit = rate_limit;
+        self
+    }
+
+    pub fn build(self) -> Result<SpinShareClient, reqwest::Error> {
+        let http = reqwest::Client::builder()
+            .user_agent(USER_AGENT)
+            .connect_timeout(CONNECT_TIMEOUT.min(self.timeout))
+            .build()?;
+        Ok(SpinShareClient {
+            http,
+            base_url: normalize_base_url(self.base_url),
+            timeout: self.timeout,
+            cache: self.cache.map(Arc::new),
+            memo: Arc::default(),
+            retry: self.retry,
+            limiter: Arc::new(RateLimiter::new(&self.rate_limit)),
+            connectivity: Arc::default(),
+            force_refresh: false,
+        })
+    }
+}
+
+fn parse_response<T: DeserializeOwned>(body: &[u8]) -> Result<T, SpinexusError> {
+    let res = serde_json::from_slice::<SpinRequest>(body)?;
+    if !KNOWN_API_VERSIONS.contains(&res.version) && !WARNED_UNKNOWN_VERSION.swap(true, Ordering::Relaxed) {
+        eprintln!("Warning: SpinShare API version {} is unknown to this client, responses may not be read correctly", res.version);
+    }
+    if res.status != 200 {
+        return Err(SpinexusError::from_api_status(res.status));
+    }
+    Ok(T::deserialize(res.data)?)
+}
+
+fn header_value(res: &reqwest::Response, name: HeaderName) -> Option<String> {
+    res.headers().get(name)?.to_str().ok().map(str::to_string)
+}
+
+/// Percent-encodes everything but unreserved characters, so that `value` stays a
+/// single path segment.
+fn path_segment(value: &str) -> String {
+    value.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+/// Whether a request failed because SpinShare couldn't be reached at all.
+fn is_offline_error(err: &reqwest::Error) -> bool {
+    err.is_connect() || err.is_timeout()
+}
+
+/// Whether a response body is a SpinShare envelope reporting success, and so worth caching.
+fn is_success_envelope(body: &[u8]) -> bool {
+    #[derive(Deserialize)]
+    struct Status {
+        status: i32,
+    }
+
+    matches!(serde_json::from_slice::<Status>(body), Ok(Status { status: 200 }))
+}
+
+fn normalize_base_url(base_url: String) -> String {
+    base_url.trim_end_matches('/').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::test_server::{MockResponse, MockServer, TempDir};
+
+    fn client(server: &MockServer, max_retries: u32) -> SpinShareClient {
+        SpinShareClient::builder()
+            .base_url(&server.url)
+            .retry_policy(RetryPolicy {
+                max_retries,
+                base_delay_ms: 10,
+                max_delay_ms: 1_000,
+            })
+            .build()
+            .unwrap()
+    }
+
+    const USER: &str = r#"{"id":1,"username":"someone","avatar":"","isVerified":false,"isPatreon":false}"#;
+
+    #[tokio::test]
+    async fn retries_server_errors_until_success() {
+        let server = MockServer::start(|i, _| match i {
+            0 | 1 => MockResponse::new(503, ""),
+            _ => MockResponse::envelope(USER),
+        });
+        let user = client(&server, 3).get_user(1).await.unwrap();
+        assert_eq!(user.id, 1);
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        assert!(requests.iter().all(|r| r.method == "GET" && r.path == "/user/1"));
+        assert_eq!(requests[2].header("user-agent"), Some(USER_AGENT));
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_retries() {
+        let server = MockServer::start(|_, _| MockResponse::new(500, ""));
+        let err = client(&server, 2).get_user(1).await.unwrap_err();
+        assert!(matches!(err, SpinexusError::HttpStatus { status, .. } if status == StatusCode::INTERNAL_SERVER_ERROR));
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn honors_retry_after() {
+        let server = MockServer::start(|i, _| match i {
+            0 => MockResponse::new(429, "").header("Retry-After", "1"),
+            _ => MockResponse::envelope(USER),
+        });
+        client(&server, 3).get_user(1).await.unwrap();
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[1].received_at - requests[0].received_at >= Duration::from_millis(950));
+    }
+
+    #[tokio::test]
+    async fn fails_when_retry_after_exceeds_max_delay() {
+        let server = MockServer::start(|_, _| MockResponse::new(429, "").header("Retry-After", "60"));
+        assert!(client(&server, 3).get_user(1).await.is_err());
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_client_errors() {
+        let server = MockServer::start(|_, _| MockResponse::new(404, ""));
+        assert!(client(&server, 3).get_user(1).await.is_err());
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_posts() {
+        let server = MockServer::start(|_, _| MockResponse::new(503, ""));
+        assert!(client(&server, 3).search_users("someone".into()).await.is_err());
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, "POST");
+        assert_eq!(requests[0].path, "/searchUsers");
+        assert_eq!(requests[0].body, br#"{"searchQuery":"someone"}"#);
+    }
+
+    #[tokio::test]
+    async fn serves_stale_cache_while_offline() {
+        // Nothing listens on a port taken from a listener that was dropped.
+        let url = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}", listener.local_addr().unwrap())
+        };
+        let dir = TempDir::new();
+        let cache = ResponseCache::new(dir.to_path_buf());
+        let endpoint = format!("{url}/user/1");
+        let stale = CachedResponse {
+            fetched_at: std::time::UNIX_EPOCH,
+            ..CachedResponse::new(format!(r#"{{"version":1,"status":200,"data":{USER}}}"#).into_bytes(), None, None)
+        };
+        cache.put(&ResponseCache::key(&endpoint, None), &endpoint, &stale).unwrap();
+
+        let client = SpinShareClient::builder()
+            .base_url(url)
+            .cache(cache)
+            .retry_policy(RetryPolicy { max_retries: 0, ..RetryPolicy::default() })
+            .build()
+            .unwrap();
+        assert_eq!(client.get_user(1).await.unwrap().id, 1);
+        assert!(!client.connectivity().is_online());
+        assert!(client.get_user(2).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn file_downloads_outlast_the_api_timeout() {
+        let server = MockServer::start(|_, _| {
+            std::thread::sleep(Duration::from_millis(400));
+            MockResponse::envelope(USER)
+        });
+        let client = SpinShareClient::builder()
+            .base_url(&server.url)
+            .timeout(Duration::from_millis(200))
+            .retry_policy(RetryPolicy { max_retries: 0, ..RetryPolicy::default() })
+            .build()
+            .unwrap();
+
+        assert!(matches!(client.get_user(1).await, Err(SpinexusError::Network(err)) if err.is_timeout()));
+        let file = client.get_file(&format!("{}/file.zip", server.url)).await.unwrap();
+        assert_eq!(file.bytes().await.unwrap(), MockResponse::envelope(USER).body);
+    }
+
+    #[tokio::test]
+    async fn shares_concurrent_requests() {
+        // Slow enough for every call to start while the first one is in flight.
+        let server = MockServer::start(|_, _| {
+            std::thread::sleep(Duration::from_millis(200));
+            MockResponse::envelope(USER)
+        });
+        let client = client(&server, 0);
+
+        let users = futures_util::future::join_all((0..12).map(|_| client.get_user(1))).await;
+        assert!(users.iter().all(|user| user.as_ref().unwrap().id == 1));
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    fn cached_client(server: &MockServer, dir: &TempDir) -> SpinShareClient {
+        SpinShareClient::builder()
+            .base_url(&server.url)
+            .cache(ResponseCache::new(dir.to_path_buf()))
+            .retry_policy(RetryPolicy { max_retries: 0, ..RetryPolicy::default() })
+            .build()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn revalidates_stale_cache_entries() {
+        const LAST_MODIFIED: &str = "Wed, 21 Oct 2015 07:28:00 GMT";
+        let server = MockServer::start(|_, _| MockResponse::new(304, ""));
+        let dir = TempDir::new();
+        let cache = ResponseCache::new(dir.to_path_buf());
+        let endpoint = format!("{}/user/1", server.url);
+        let key = ResponseCache::key(&endpoint, None);
+        let stale = CachedResponse {
+            fetched_at: std::time::UNIX_EPOCH,
+            ..CachedResponse::new(MockResponse::envelope(USER).body, Some("\"v1\"".into()), Some(LAST_MODIFIED.into()))
+        };
+        cache.put(&key, &endpoint, &stale).unwrap();
+
+        assert_eq!(cached_client(&server, &dir).get_user(1).await.unwrap().id, 1);
+        let requests = server.requests();
+        assert_eq!(requests[0].header("if-none-match"), Some("\"v1\""));
+        assert_eq!(requests[0].header("if-modified-since"), Some(LAST_MODIFIED));
+        // The entry counts as fresh again, so the next client doesn't ask at all.
+        assert!(cache.get(&key).unwrap().is_fresh(ttl::USER));
+        assert_eq!(cached_client(&server, &dir).get_user(1).await.unwrap().id, 1);
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn caches_only_successful_envelopes() {
+        let server = MockServer::start(|_, req| match req.path.as_str() {
+            "/user/1" => MockResponse::envelope(USER).header("ETag", "\"v1\""),
+            _ => MockResponse::new(200, r#"{"version":1,"status":404,"data":[]}"#),
+        });
+        let dir = TempDir::new();
+        let client = cached_client(&server, &dir);
+        let cache = ResponseCache::new(dir.to_path_buf());
+
+        client.get_user(1).await.unwrap();
+        let cached = cache.get(&ResponseCache::key(&format!("{}/user/1", server.url), None)).unwrap();
+        assert_eq!(cached.etag.as_deref(), Some("\"v1\""));
+
+        assert!(matches!(client.get_user(2).await, Err(SpinexusError::NotFound)));
+        assert!(cache.get(&ResponseCache::key(&format!("{}/user/2", server.url), None)).is_none());
+    }
+
+    #[tokio::test]
+    async fn detects_last_page() {
+        let chart = r#"{"id":1,"title":"t","subtitle":null,"artist":"a","charter":"c","cover":""}"#;
+        let page = move |n: usize| format!("[{}]", vec![chart; n].join(","));
+        // 12 + 12 + 3 charts for new charts, exactly 12 for updated ones.
+        let server = MockServer::start(move |_, req| match req.path.as_str() {
+            "/songs/new/0" | "/songs/new/1" | "/songs/updated/0" => MockResponse::envelope(&page(PAGE_SIZE)),
+            "/songs/new/2" => MockResponse::envelope(&page(3)),
+            _ => MockResponse::envelope("[]"),
+        });
+        let client = client(&server, 0);
+
+        let first = client.get_chart_page(ChartList::New, 0).await.unwrap();
+        assert!(!first.is_last);
+        assert_eq!(first.page_count, None);
+        let last = client.get_chart_page(ChartList::New, 2).await.unwrap();
+        assert_eq!(last.items.len(), 3);
+        assert!(last.is_last);
+        assert_eq!(last.page_count, Some(3));
+
+        let updated = client.get_chart_page(ChartList::Updated, 0).await.unwrap();
+        assert!(updated.is_last);
+        assert_eq!(updated.page_count, Some(1));
+    }
+
+    #[tokio::test]
+    async fn looks_up_charts_by_reference() {
+        let server = MockServer::start(|_, _| MockResponse::new(404, ""));
+        let client = client(&server, 0);
+        let _ = client.get_chart_by_reference("spinshare_5f1a.srtb").await;
+        let _ = client.get_chart_by_reference("some chart/../x").await;
+        let paths: Vec<_> = server.requests().into_iter().map(|r| r.path).collect();
+        assert_eq!(paths, ["/song/spinshare_5f1a", "/song/some%20chart%2F..%2Fx"]);
+    }
+
+    #[tokio::test]
+    async fn rate_limits_requests() {
+        let server = MockServer::start(|_, _| MockResponse::new(200, "zip"));
+        let client = SpinShareClient::builder()
+            .base_url(&server.url)
+            .rate_limit(RateLimit {
+                requests_per_second: 10.0,
+                burst: 2,
+            })
+            .build()
+            .unwrap();
+        let start = Instant::now();
+        for _ in 0..4 {
+            client.get_file(&format!("{}/file.zip", server.url)).await.unwrap();
+        }
+        // Two requests go out immediately, the other two wait 100ms each.
+        assert!(start.elapsed() >= Duration::from_millis(190));
+    }
+}
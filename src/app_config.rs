@@ -1,15 +1,36 @@
 use confy::ConfyError;
 use serde::{Serialize, Deserialize};
 
+use crate::api::{DEFAULT_API_URL, DEFAULT_TIMEOUT};
+use crate::install::{ArchiveLimits, ConflictPolicy};
+use crate::retry::{RateLimit, RetryPolicy};
+
 #[derive(Serialize, Deserialize)]
+#[serde(default)]
 pub struct AppConfig {
     pub customs_path: String,
+    pub api_url: String,
+    pub api_timeout_secs: u64,
+    pub retry: RetryPolicy,
+    pub rate_limit: RateLimit,
+    /// How many charts are downloaded at the same time.
+    pub max_concurrent_downloads: usize,
+    pub archive_limits: ArchiveLimits,
+    /// What to do when a chart has files that another one already installed differently.
+    pub conflict_policy: ConflictPolicy,
 }
 
 impl Default for AppConfig {
     fn default() -> Self {
         Self {
             customs_path: "".into(),
+            api_url: DEFAULT_API_URL.into(),
+            api_timeout_secs: DEFAULT_TIMEOUT.as_secs(),
+            retry: RetryPolicy::default(),
+            rate_limit: RateLimit::default(),
+            max_concurrent_downloads: 2,
+            archive_limits: ArchiveLimits::default(),
+            conflict_policy: ConflictPolicy::default(),
         }
     }
 }
@@ -0,0 +1,129 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Serialize, Deserialize};
+
+/// Disk-backed cache for API responses, keyed on the request URL and body.
+///
+/// Each entry is stored as a `<key>.json` metadata file next to a `<key>.body` file
+/// holding the raw response body.
+#[derive(Debug)]
+pub struct ResponseCache {
+    dir: PathBuf,
+}
+
+#[derive(Debug, Clone)]
+pub struct CachedResponse {
+    pub body: Vec<u8>,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub fetched_at: SystemTime,
+}
+
+#[derive(Serialize, Deserialize)]
+struct EntryMeta {
+    url: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    /// Seconds since the Unix epoch.
+    fetched_at: u64,
+}
+
+impl CachedResponse {
+    pub fn new(body: Vec<u8>, etag: Option<String>, last_modified: Option<String>) -> Self {
+        Self {
+            body,
+            etag,
+            last_modified,
+            fetched_at: SystemTime::now(),
+        }
+    }
+
+    pub fn age(&self) -> Duration {
+        SystemTime::now().duration_since(self.fetched_at).unwrap_or_default()
+    }
+
+    pub fn is_fresh(&self, ttl: Duration) -> bool {
+        self.age() < ttl
+    }
+}
+
+impl ResponseCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+        }
+    }
+
+    /// Cache stored in the `api` folder of the app's cache directory.
+    pub fn in_cache_dir() -> Self {
+        Self::new(PathBuf::from(crate::download::cache_dir()).join("api"))
+    }
+
+    /// Builds the cache key for a request. Bodies are part of the key so that
+    /// different searches against the same endpoint don't collide.
+    pub fn key(url: &str, body: Option<&[u8]>) -> String {
+        // FNV-1a, which unlike `DefaultHasher` is stable across Rust versions.
+        let mut hash: u64 = 0xcbf29ce484222325;
+        let bytes = url.as_bytes().iter().chain(&[0]).chain(body.unwrap_or_default());
+        for byte in bytes {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        format!("{hash:016x}")
+    }
+
+    fn meta_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{key}.json"))
+    }
+
+    fn body_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{key}.body"))
+    }
+
+    pub fn get(&self, key: &str) -> Option<CachedResponse> {
+        let meta = fs::read(self.meta_path(key)).ok()?;
+        let meta: EntryMeta = serde_json::from_slice(&meta).ok()?;
+        let body = fs::read(self.body_path(key)).ok()?;
+        Some(CachedResponse {
+            body,
+            etag: meta.etag,
+            last_modified: meta.last_modified,
+            fetched_at: UNIX_EPOCH + Duration::from_secs(meta.fetched_at),
+        })
+    }
+
+    pub fn put(&self, key: &str, url: &str, response: &CachedResponse) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let meta = EntryMeta {
+            url: url.into(),
+            etag: response.etag.clone(),
+            last_modified: response.last_modified.clone(),
+            fetched_at: response.fetched_at.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(),
+        };
+        // Entries are only read back when their metadata exists, so dropping it first
+        // means a crash halfway through can't pair a new body with stale validators.
+        match fs::remove_file(self.meta_path(key)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        write_atomic(&self.body_path(key), &response.body)?;
+        write_atomic(&self.meta_path(key), &serde_json::to_vec(&meta)?)
+    }
+
+    pub fn clear(&self) -> io::Result<()> {
+        match fs::remove_dir_all(&self.dir) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    fs::write(&tmp, contents)?;
+    fs::rename(&tmp, path)
+}
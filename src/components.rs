@@ -1,26 +1,121 @@
 use dioxus::prelude::*;
 use dioxus_router::components::{Link, GoBackButton, GoForwardButton};
+use dioxus_router::hooks::use_navigator;
 
-use std::path::PathBuf;
+use chrono::{DateTime, Utc};
 
+use std::time::Duration;
+
+use crate::api::SpinShareClient;
 use crate::app_config::AppConfig;
+use crate::error::SpinexusError;
 use crate::route::Route;
 use crate::models::*;
-use crate::download::download_and_extract_zip;
+use crate::download_manager::{DownloadJob, DownloadManager, JobId, JobState};
+use crate::install::ConflictResolution;
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Clone, Debug)]
 pub enum ChartListingMode {
-    New(i32),
-    Updated(i32),
-    HotMonth(i32),
-    HotWeek(i32),
+    List(ChartList),
     User(i32),
-    SearchChart(String, i32),
+    SearchChart(String, SearchFilters),
+}
+
+/// Returns a handle to the shared SpinShare client provided by `App`.
+pub fn use_spinshare(cx: &ScopeState) -> SpinShareClient {
+    use_shared_state::<SpinShareClient>(cx).unwrap().read().clone()
+}
+
+/// Returns whether SpinShare is reachable, re-rendering the component when that changes.
+pub fn use_online(cx: &ScopeState) -> bool {
+    let client = use_spinshare(cx);
+    let online = use_state(cx, || client.connectivity().is_online());
+    use_future(cx, (), |_| {
+        let online = online.clone();
+        let mut status = client.connectivity().subscribe();
+        async move {
+            loop {
+                online.set(*status.borrow_and_update());
+                if status.changed().await.is_err() {
+                    break;
+                }
+            }
+        }
+    });
+    **online
+}
+
+/// How often to check whether SpinShare is back while offline.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(15);
+
+pub fn ConnectionBanner(cx: Scope) -> Element {
+    let client = use_spinshare(cx);
+    let online = use_online(cx);
+    let checking = use_state(cx, || false);
+
+    use_future(cx, (&online,), |(online,)| {
+        let client = client.clone();
+        async move {
+            if online {
+                return;
+            }
+            loop {
+                tokio::time::sleep(RECONNECT_INTERVAL).await;
+                if client.check_connection().await {
+                    break;
+                }
+            }
+        }
+    });
+
+    if online {
+        return None;
+    }
+    render! {
+        div {
+            class: "flex items-center rounded bg-yellow-100 text-yellow-900 px-3 py-1 m-1",
+            p {
+                "SpinShare can't be reached. Pages show data saved from earlier visits, which may be out of date, and downloads are unavailable."
+            }
+            button {
+                class: "btn btn-outline-blue ml-auto",
+                disabled: **checking,
+                onclick: move |_| {
+                    let client = client.clone();
+                    let checking = checking.clone();
+                    checking.set(true);
+                    async move {
+                        client.check_connection().await;
+                        checking.set(false);
+                    }
+                },
+                "Check again"
+            }
+        }
+    }
+}
+
+/// Returns the shared download manager, re-rendering the component whenever a
+/// download changes state.
+pub fn use_downloads(cx: &ScopeState) -> DownloadManager {
+    let manager = use_shared_state::<DownloadManager>(cx).unwrap().read().clone();
+    use_future(cx, (), |_| {
+        let mut changes = manager.subscribe();
+        let update = cx.schedule_update();
+        async move {
+            while changes.changed().await.is_ok() {
+                update();
+            }
+        }
+    });
+    manager
 }
 
 pub fn HeaderButtons(cx: Scope) -> Element {
+    let navigator = use_navigator(cx);
     render! {
         div {
+            class: "flex items-center",
             Link {
                 class: "btn btn-outline-blue m-3",
                 to: Route::Index {},
@@ -38,6 +133,27 @@ pub fn HeaderButtons(cx: Scope) -> Element {
                     "Forward >"
                 }
             }
+            Link {
+                class: "btn btn-outline-blue m-3",
+                to: Route::Downloads {},
+                "Downloads"
+            }
+            form {
+                class: "ml-auto",
+                onsubmit: move |event| {
+                    if let Some(query) = event.values.get("quick-search").map(|q| q[0].trim()) {
+                        if !query.is_empty() {
+                            navigator.push(Route::Search { query: query.to_string() });
+                        }
+                    }
+                },
+                input {
+                    class: "border rounded px-2 py-1 m-3",
+                    name: "quick-search",
+                    r#type: "search",
+                    placeholder: "Search charts and users",
+                }
+            }
         }
     }
 }
@@ -51,36 +167,362 @@ pub fn ShowLoading(cx: Scope) -> Element {
     }
 }
 
+/// Chart search box with difficulty, rating and explicit filters.
+#[inline_props]
+pub fn SearchForm(cx: Scope, query: String, filters: SearchFilters) -> Element {
+    let navigator = use_navigator(cx);
+    let SearchFilters {
+        diff_rating_from,
+        diff_rating_to,
+        show_explicit,
+        ..
+    } = filters;
+
+    render! {
+        form {
+            class: "flex flex-wrap items-center m-1",
+            onsubmit: move |event| {
+                let values = &event.values;
+                let query = match values.get("search-query") {
+                    Some(q) if !q[0].trim().is_empty() => q[0].trim().to_string(),
+                    _ => return,
+                };
+                let mut filters = SearchFilters {
+                    show_explicit: values.contains_key("show-explicit"),
+                    ..Default::default()
+                };
+                for difficulty in Difficulty::ALL {
+                    filters.set_includes(difficulty, values.contains_key(&format!("diff-{}", difficulty.name().to_lowercase())));
+                }
+                if let Some(from) = values.get("rating-from").and_then(|v| v[0].parse().ok()) {
+                    filters.diff_rating_from = from;
+                }
+                if let Some(to) = values.get("rating-to").and_then(|v| v[0].parse().ok()) {
+                    filters.diff_rating_to = to;
+                }
+                navigator.push(Route::SearchCharts { query, filters });
+            },
+            input {
+                class: "border rounded px-2 py-1 m-1",
+                name: "search-query",
+                r#type: "text",
+                placeholder: "Search charts",
+                value: "{query}",
+            }
+            for difficulty in Difficulty::ALL {
+                label {
+                    class: "m-1",
+                    input {
+                        r#type: "checkbox",
+                        name: "diff-{difficulty.name().to_lowercase()}",
+                        checked: filters.includes(difficulty),
+                    }
+                    " {difficulty.name()}"
+                }
+            }
+            label {
+                class: "m-1",
+                "Rating "
+                input {
+                    class: "border rounded w-16 px-1",
+                    r#type: "number",
+                    name: "rating-from",
+                    min: 0,
+                    value: "{diff_rating_from}",
+                }
+                " to "
+                input {
+                    class: "border rounded w-16 px-1",
+                    r#type: "number",
+                    name: "rating-to",
+                    min: 0,
+                    value: "{diff_rating_to}",
+                }
+            }
+            label {
+                class: "m-1",
+                input {
+                    r#type: "checkbox",
+                    name: "show-explicit",
+                    checked: *show_explicit,
+                }
+                " Show explicit"
+            }
+            input {
+                class: "btn btn-blue m-1",
+                r#type: "submit",
+                value: "Search",
+            }
+        }
+    }
+}
+
 #[inline_props]
-fn UserShortDisplay(cx: Scope, id: i32) -> Element {
-    let user = use_future(cx, (), |_| get_user(*id));
+pub fn ShowError<'a>(cx: Scope<'a>, context: &'a str, error: &'a SpinexusError, onretry: EventHandler<'a>) -> Element<'a> {
+    render! {
+        div {
+            class: "text-center",
+            p {
+                "{context}: {error}"
+            }
+            if error.is_retryable() {
+                rsx! {
+                    button { class: "btn btn-blue m-1", onclick: move |_| onretry.call(()), "Retry" }
+                }
+            }
+        }
+    }
+}
+
+#[inline_props]
+fn UserShortDisplay<'a>(cx: Scope, id: i32, label: &'a str) -> Element {
+    let client = use_spinshare(cx);
+    let user = use_future(cx, (id,), |(id,)| async move { client.get_user(id).await });
     match user.value() {
         Some(Ok(user)) => {
-            let User {
-                username,
-                avatar,
-                ..
-            } = user;
+            render! {
+                UserCard { user: user, label: label }
+            }
+        }
+        Some(Err(err)) => {
+            render! {
+                ShowError { context: "Could not load uploader", error: err, onretry: move |_| user.restart() }
+            }
+        }
+        None => {
+            render! {"wut"}
+        }
+    }
+}
+
+#[inline_props]
+fn UserCard<'a>(cx: Scope, user: &'a User, label: &'a str) -> Element {
+    let User {
+        id,
+        username,
+        avatar,
+        ..
+    } = user;
+
+    render! {
+        div {
+            class: "relative rounded bg-gray-200 no-underline m-1 max-w-sm overflow-hidden",
+            Link {
+                to: Route::User { id: *id },
+                div {
+                    class: "flex items-center",
+                    img {
+                        class: "absolute aspect-square rounded-full shadow-lg w-28 h-28 -left-6",
+                        src: "{avatar}"
+                    }
+                    div {
+                        class: "min-w-0 py-5 pl-28",
+                        p {
+                            class: "font-bold text-gray-900",
+                            "{username}"
+                        }
+                        p {
+                            class: "text-sm font-medium text-slate-700",
+                            "{label}"
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Copy)]
+enum SearchTab {
+    Charts,
+    Users,
+}
+
+/// Chart and user results for a search query, in tabs.
+#[inline_props]
+pub fn SearchAllResults(cx: Scope, query: String) -> Element {
+    let client = use_spinshare(cx);
+    let results = use_future(cx, (query,), |(query,)| async move { client.search_all(query).await });
+    let tab = use_state(cx, || SearchTab::Charts);
+    let tab_class = |t: SearchTab| if **tab == t { "btn btn-blue m-1" } else { "btn btn-outline-blue m-1" };
+
+    match results.value() {
+        Some(Ok(results)) => {
             render! {
                 div {
-                    class: "relative rounded bg-gray-200 no-underline m-1 max-w-sm overflow-hidden",
-                    Link {
-                        to: Route::User { id: *id },
-                        div {
-                            class: "flex items-center",
-                            img {
-                                class: "absolute aspect-square rounded-full shadow-lg w-28 h-28 -left-6",
-                                src: "{avatar}"
+                    button { class: tab_class(SearchTab::Charts), onclick: move |_| tab.set(SearchTab::Charts), "Charts" }
+                    button { class: tab_class(SearchTab::Users), onclick: move |_| tab.set(SearchTab::Users), "Users" }
+                }
+                match **tab {
+                    SearchTab::Charts => rsx! { ChartSearchResults { query: query, charts: &results.charts } },
+                    SearchTab::Users => rsx! { UserSearchResults { query: query, users: &results.users } },
+                }
+            }
+        }
+        Some(Err(err)) => {
+            render! {
+                ShowError { context: "An error occurred while searching", error: err, onretry: move |_| results.restart() }
+            }
+        }
+        None => {
+            render! {
+                ShowLoading {}
+            }
+        }
+    }
+}
+
+#[inline_props]
+fn ChartSearchResults<'a>(cx: Scope, query: &'a str, charts: &'a [PartialChart]) -> Element {
+    if charts.is_empty() {
+        return render! { "No charts found for {query}" };
+    }
+    render! {
+        div {
+            class: "grid grid-cols-3",
+            for chart in charts.iter() {
+                ChartShortDisplay { chart: chart }
+            }
+        }
+    }
+}
+
+#[inline_props]
+fn UserSearchResults<'a>(cx: Scope, query: &'a str, users: &'a [User]) -> Element {
+    if users.is_empty() {
+        return render! { "No users found for {query}" };
+    }
+    render! {
+        div {
+            class: "grid grid-cols-3",
+            for user in users.iter() {
+                UserCard { user: user, label: if user.is_verified { "Verified user" } else { "User" } }
+            }
+        }
+    }
+}
+
+#[inline_props]
+pub fn PlaylistFullDisplay<'a>(cx: Scope, playlist: &'a Playlist) -> Element {
+    let app_config = use_shared_state::<AppConfig>(cx).unwrap();
+    let downloads = use_downloads(cx);
+    let online = use_online(cx);
+    let has_customs = !app_config.read().customs_path.is_empty();
+
+    let Playlist {
+        title,
+        description,
+        user,
+        cover,
+        songs,
+        ..
+    } = playlist;
+
+    let total = songs.len();
+    let charts: Vec<(i32, String)> = songs.iter().map(|c| (c.id, c.title.clone())).collect();
+    let jobs: Vec<DownloadJob> = songs.iter().filter_map(|c| downloads.job_for_chart(c.id)).collect();
+    let installed = jobs.iter().filter(|job| matches!(job.state, JobState::Done(_))).count();
+    let pending: Vec<JobId> = jobs.iter().filter(|job| !job.state.is_finished()).map(|job| job.id).collect();
+    let has_pending = !pending.is_empty();
+    let cancel_manager = downloads.clone();
+
+    render! {
+        div {
+            class: "flex items-center space-x-4",
+            img {
+                class: "aspect-square rounded-lg w-40",
+                src: "{cover}"
+            }
+            div {
+                class: "space-y-1",
+                div {
+                    class: "text-3xl font-bold",
+                    "{title}"
+                }
+                p {
+                    class: "whitespace-pre-line",
+                    "{description}"
+                }
+                UserShortDisplay { id: *user, label: "Playlist creator" }
+                button {
+                    class: "btn btn-blue",
+                    disabled: !online || !has_customs || has_pending,
+                    onclick: move |_| {
+                        let destination = app_config.read().customs_path.clone();
+                        for (id, title) in &charts {
+                            downloads.enqueue(*id, title.clone(), destination.clone());
+                        }
+                    },
+                    "Download all"
+                }
+                if has_pending {
+                    rsx! {
+                        button {
+                            class: "btn btn-outline-blue m-1",
+                            onclick: move |_| {
+                                for id in &pending {
+                                    cancel_manager.cancel(*id);
+                                }
+                            },
+                            "Cancel remaining"
+                        }
+                    }
+                }
+                if !jobs.is_empty() {
+                    rsx! {
+                        p {
+                            class: "text-sm text-gray-700",
+                            "Installed {installed} of {total} charts"
+                        }
+                    }
+                }
+                for job in &jobs {
+                    if let JobState::Failed(e) = &job.state {
+                        rsx! {
+                            p {
+                                class: "text-sm text-red-700",
+                                "{job.title}: {e}"
                             }
+                        }
+                    }
+                }
+            }
+        }
+        div {
+            class: "grid grid-cols-3",
+            for chart in songs {
+                ChartShortDisplay { chart: chart }
+            }
+        }
+    }
+}
+
+#[inline_props]
+fn UserPlaylists(cx: Scope, id: i32) -> Element {
+    let client = use_spinshare(cx);
+    let playlists = use_future(cx, (id,), |(id,)| async move { client.get_playlists_for_user(id).await });
+
+    match playlists.value() {
+        Some(Ok(playlists)) if playlists.is_empty() => {
+            render! { "No playlists" }
+        }
+        Some(Ok(playlists)) => {
+            render! {
+                div {
+                    class: "flex flex-wrap",
+                    for playlist in playlists {
+                        Link {
+                            key: "{playlist.id}",
+                            to: Route::Playlist { id: playlist.id },
                             div {
-                                class: "min-w-0 py-5 pl-28",
-                                p {
-                                    class: "font-bold text-gray-900",
-                                    "{username}"
+                                class: "rounded-xl bg-gray-200 m-1 p-2 w-48",
+                                img {
+                                    class: "aspect-square rounded-lg w-full",
+                                    src: "{playlist.cover}"
                                 }
                                 p {
-                                    class: "text-sm font-medium text-slate-700",
-                                    "Uploader"
+                                    class: "font-semibold",
+                                    "{playlist.title}"
                                 }
                             }
                         }
@@ -89,10 +531,14 @@ fn UserShortDisplay(cx: Scope, id: i32) -> Element {
             }
         }
         Some(Err(err)) => {
-            render! {"Err {err}"}
+            render! {
+                ShowError { context: "An error occurred while fetching playlists", error: err, onretry: move |_| playlists.restart() }
+            }
         }
         None => {
-            render! {"wut"}
+            render! {
+                ShowLoading {}
+            }
         }
     }
 }
@@ -103,17 +549,94 @@ pub fn UserFullDisplay<'a>(cx: Scope, user: &'a User) -> Element {
         id,
         username,
         avatar,
+        about,
+        pronouns,
+        is_verified,
+        is_patreon,
+        badges,
+        cards,
+        upload_count,
+        playlist_count,
+        review_count,
         ..
     } = user;
 
     render! {
         div {
+            class: "flex items-center space-x-4",
             img {
+                class: "aspect-square rounded-full w-32 h-32",
                 src: "{avatar}"
             }
             div {
-                "{username}"
+                class: "space-y-1",
+                div {
+                    class: "flex items-center text-3xl font-bold",
+                    "{username}"
+                    if *is_verified {
+                        rsx! { span { class: "badge badge-verified", title: "Verified", "Verified" } }
+                    }
+                    if *is_patreon {
+                        rsx! { span { class: "badge badge-patreon", title: "Patreon supporter", "Patreon" } }
+                    }
+                }
+                if let Some(pronouns) = pronouns {
+                    rsx! { p { class: "text-gray-600", "{pronouns}" } }
+                }
+                div {
+                    class: "flex flex-wrap",
+                    for badge in badges {
+                        img {
+                            class: "w-6 h-6 m-0.5",
+                            src: "{badge.icon}",
+                            title: "{badge.title}: {badge.description}",
+                        }
+                    }
+                }
+                p {
+                    class: "text-sm text-gray-700",
+                    "{upload_count} uploads - {playlist_count} playlists - {review_count} reviews"
+                }
+            }
+        }
+        if let Some(about) = about {
+            rsx! {
+                p {
+                    class: "whitespace-pre-line my-2",
+                    "{about}"
+                }
+            }
+        }
+        if !cards.is_empty() {
+            rsx! {
+                h2 {
+                    "Cards"
+                }
+                div {
+                    class: "flex flex-wrap",
+                    for card in cards {
+                        div {
+                            key: "{card.id}",
+                            class: "rounded bg-gray-200 m-1 p-2 w-40 text-center",
+                            title: "{card.description}",
+                            img {
+                                class: "mx-auto w-20 h-20",
+                                src: "{card.icon}"
+                            }
+                            p {
+                                class: "font-semibold",
+                                "{card.title}"
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        div {
+            h2 {
+                "Playlists"
             }
+            UserPlaylists { id: *id }
         }
         div {
             h2 {
@@ -127,18 +650,39 @@ pub fn UserFullDisplay<'a>(cx: Scope, user: &'a User) -> Element {
 #[inline_props]
 pub fn ChartFullDisplay<'a>(cx: Scope, chart: &'a FullChart) -> Element {
     let app_config = use_shared_state::<AppConfig>(cx).unwrap();
+    let download_manager = use_downloads(cx);
+    let online = use_online(cx);
+    let has_customs = !app_config.read().customs_path.is_empty();
 
     let FullChart {
+        id,
         title,
         artist,
         charter,
         uploader,
         cover,
-        file_reference,
+        description,
+        tags,
+        is_explicit,
+        difficulties,
+        upload_date,
+        update_date,
+        views,
+        downloads,
+        is_draft,
         ..
     } = chart;
 
-    let zip = &chart.paths.zip;
+    let job = download_manager.job_for_chart(*id);
+    let mut details = vec![];
+    if let Some(date) = upload_date {
+        details.push(format!("Uploaded {}", date.format("%Y-%m-%d")));
+    }
+    if let Some(date) = update_date {
+        details.push(format!("Updated {}", date.format("%Y-%m-%d")));
+    }
+    details.push(format!("{views} views, {downloads} downloads"));
+    let details = details.join(" - ");
 
     render! {
         div {
@@ -158,28 +702,350 @@ pub fn ChartFullDisplay<'a>(cx: Scope, chart: &'a FullChart) -> Element {
             div {
                 "Charted by {charter}"
             }
-            UserShortDisplay { id: *uploader }
-            button {
-                onclick: move |_| {
-                    let zip = zip.clone();
-                    let destination = app_config.read().customs_path.clone();
-                    let file_reference = file_reference.clone();
-                    let proj_dir = directories::ProjectDirs::from("rs", "", "spinexus").unwrap();
-                    let cache_dir = PathBuf::from(proj_dir.cache_dir()).to_str().unwrap().to_string();
-                    async {
-                        println!("Downloading file {zip}");
-                        match download_and_extract_zip(zip, cache_dir, destination, file_reference).await {
-                            Ok(_) => println!("Download complete!"),
-                            Err(e) => println!("Error {e}"),
-                        };
+            div {
+                class: "flex flex-wrap items-center",
+                DifficultyBadges { difficulties: difficulties }
+                if *is_explicit {
+                    rsx! { span { class: "badge badge-explicit", "Explicit" } }
+                }
+                if *is_draft {
+                    rsx! { span { class: "badge badge-draft", "Draft" } }
+                }
+            }
+            div {
+                class: "text-sm text-gray-600",
+                "{details}"
+            }
+            if !tags.is_empty() {
+                rsx! {
+                    div {
+                        class: "flex flex-wrap",
+                        for tag in tags {
+                            span { class: "badge badge-tag", "{tag}" }
+                        }
                     }
-                },
-                "Download"
+                }
+            }
+            if !description.is_empty() {
+                rsx! {
+                    p {
+                        class: "whitespace-pre-line my-2",
+                        "{description}"
+                    }
+                }
+            }
+            UserShortDisplay { id: *uploader, label: "Uploader" }
+            div {
+                class: "flex items-center",
+                button {
+                    class: "btn btn-blue m-1",
+                    disabled: !online || !has_customs || job.as_ref().is_some_and(|job| !job.state.is_finished()),
+                    onclick: move |_| {
+                        let destination = app_config.read().customs_path.clone();
+                        download_manager.enqueue(*id, title.clone(), destination);
+                    },
+                    "Download"
+                }
+                if let Some(job) = &job {
+                    rsx! {
+                        JobStatus { state: job.state.clone() }
+                        if !job.state.is_finished() {
+                            rsx! { CancelDownload { id: job.id } }
+                        }
+                    }
+                }
+            }
+            div {
+                h2 {
+                    class: "text-2xl font-bold mt-4",
+                    "Reviews"
+                }
+                ChartReviews { id: *id }
+            }
+            div {
+                h2 {
+                    class: "text-2xl font-bold mt-4",
+                    "SpinPlays"
+                }
+                ChartSpinPlays { id: *id }
+            }
+        }
+    }
+}
+
+#[inline_props]
+pub fn JobStatus(cx: Scope, state: JobState) -> Element {
+    match state {
+        JobState::Queued => render! { span { class: "text-gray-600", "Queued" } },
+        JobState::Downloading(progress) => {
+            let received = format_bytes(progress.received);
+            let size = match progress.total {
+                Some(total) => format!("{received} of {}", format_bytes(total)),
+                None => received,
+            };
+            let rate = match progress.bytes_per_sec {
+                0.0 => String::new(),
+                rate => format!(" at {}/s", format_bytes(rate as u64)),
+            };
+            let eta = match progress.eta() {
+                Some(eta) => format!(", {} left", format_duration(eta)),
+                None => String::new(),
+            };
+            render! {
+                div {
+                    class: "w-64",
+                    ProgressBar { fraction: progress.fraction() }
+                    span { class: "text-sm text-gray-700", "Downloading {size}{rate}{eta}" }
+                }
+            }
+        }
+        JobState::Extracting(progress) => {
+            let fraction = (progress.total > 0).then(|| progress.entries as f64 / progress.total as f64);
+            render! {
+                div {
+                    class: "w-64",
+                    ProgressBar { fraction: fraction }
+                    span { class: "text-sm text-gray-700", "Extracting {progress.entries} of {progress.total} files" }
+                }
+            }
+        }
+        JobState::AwaitingDecision(files) => {
+            let count = plural(files.len(), "conflicting file");
+            render! { span { class: "text-yellow-700", "Waiting for a decision about {count}" } }
+        }
+        JobState::Done(report) => {
+            let mut changes = vec![];
+            if !report.overwritten.is_empty() {
+                changes.push(format!(", replaced {} already installed", plural(report.overwritten.len(), "file")));
+            }
+            if !report.skipped.is_empty() {
+                changes.push(format!(", kept {} already installed", plural(report.skipped.len(), "file")));
+            }
+            if !report.renamed.is_empty() {
+                changes.push(format!(", saved {} under new names", plural(report.renamed.len(), "file")));
+            }
+            let changes = changes.concat();
+            render! { span { class: "text-green-700", "Installed{changes}" } }
+        }
+        JobState::Failed(e) => render! { span { class: "text-red-700", "Failed: {e}" } },
+        JobState::Cancelled => render! { span { class: "text-gray-600", "Cancelled" } },
+    }
+}
+
+/// Horizontal bar filled up to `fraction`, or pulsing when the total isn't known.
+#[inline_props]
+fn ProgressBar(cx: Scope, #[props(!optional)] fraction: Option<f64>) -> Element {
+    match fraction {
+        Some(fraction) => {
+            let percent = (fraction.clamp(0.0, 1.0) * 100.0).round();
+            render! {
+                div {
+                    class: "w-full h-2 rounded bg-gray-200 overflow-hidden",
+                    div { class: "h-full bg-blue-500", width: "{percent}%" }
+                }
+            }
+        }
+        None => render! {
+            div { class: "w-full h-2 rounded bg-blue-200 animate-pulse" }
+        },
+    }
+}
+
+/// Every download started in this session, most recent first.
+pub fn DownloadList(cx: Scope) -> Element {
+    let downloads = use_downloads(cx);
+    let jobs = downloads.jobs();
+
+    if jobs.is_empty() {
+        return render! { "Nothing downloaded yet" };
+    }
+    render! {
+        button {
+            class: "btn btn-outline-blue m-1",
+            disabled: !jobs.iter().any(|job| job.state.is_finished()),
+            onclick: move |_| downloads.clear_finished(),
+            "Clear finished"
+        }
+        for job in jobs.iter().rev() {
+            div {
+                key: "{job.id}",
+                class: "flex items-center space-x-4 m-1",
+                Link {
+                    to: Route::Chart { id: job.chart_id },
+                    "{job.title}"
+                }
+                JobStatus { state: job.state.clone() }
+                if !job.state.is_finished() {
+                    rsx! { CancelDownload { id: job.id } }
+                }
+            }
+        }
+    }
+}
+
+/// Asks what to do when a download would change files that are already installed,
+/// whatever page is open.
+pub fn ConflictDialog(cx: Scope) -> Element {
+    let downloads = use_downloads(cx);
+    let jobs = downloads.jobs();
+    let (job, files) = jobs.iter().find_map(|job| match &job.state {
+        JobState::AwaitingDecision(files) => Some((job, files)),
+        _ => None,
+    })?;
+    let id = job.id;
+    let answer = move |resolution| {
+        let downloads = downloads.clone();
+        move |_| downloads.resolve_conflicts(id, resolution)
+    };
+
+    render! {
+        div {
+            class: "fixed inset-0 z-10 flex items-center justify-center bg-black bg-opacity-50",
+            div {
+                class: "bg-white rounded-lg shadow-lg max-w-lg p-4 space-y-2",
+                h2 {
+                    class: "text-xl font-bold",
+                    "{job.title} has files that are already installed"
+                }
+                p {
+                    "These files exist with different contents, most likely from another chart. Replacing them changes that chart too. Keeping both charts installs this chart's .srtb under a new name, with the installed versions of its other files."
+                }
+                ul {
+                    class: "list-disc pl-6 max-h-48 overflow-y-auto font-mono text-sm",
+                    for file in files {
+                        li { "{file.display()}" }
+                    }
+                }
+                div {
+                    button { class: "btn btn-blue m-1", onclick: answer(ConflictResolution::Skip), "Keep the installed ones" }
+                    button { class: "btn btn-outline-blue m-1", onclick: answer(ConflictResolution::KeepBoth), "Keep both charts" }
+                    button { class: "btn btn-outline-blue m-1", onclick: answer(ConflictResolution::Overwrite), "Replace them" }
+                    CancelDownload { id: id }
+                }
+            }
+        }
+    }
+}
+
+#[inline_props]
+fn CancelDownload(cx: Scope, id: JobId) -> Element {
+    let downloads = use_shared_state::<DownloadManager>(cx).unwrap();
+    render! {
+        button {
+            class: "btn btn-outline-blue m-1",
+            onclick: move |_| downloads.read().cancel(*id),
+            "Cancel"
+        }
+    }
+}
+
+#[inline_props]
+fn ChartReviews(cx: Scope, id: i32) -> Element {
+    let client = use_spinshare(cx);
+    let reviews = use_future(cx, (id,), |(id,)| async move { client.get_reviews(id).await });
+
+    match reviews.value() {
+        Some(Ok(reviews)) if reviews.is_empty() => {
+            render! { "No reviews yet" }
+        }
+        Some(Ok(reviews)) => {
+            let ratio = recommendation_ratio(reviews).unwrap_or_default();
+            let percent = (ratio * 100.0).round();
+            render! {
+                p {
+                    class: "font-semibold",
+                    "{percent}% of {reviews.len()} reviewers recommend this chart"
+                }
+                for review in reviews {
+                    div {
+                        key: "{review.id}",
+                        class: "rounded bg-gray-100 m-1 p-2",
+                        UserShortDisplay { id: review.user, label: "Reviewer" }
+                        p {
+                            class: if review.recommended { "font-bold text-green-700" } else { "font-bold text-red-700" },
+                            if review.recommended { "Recommended" } else { "Not recommended" }
+                        }
+                        if let Some(date) = review.review_date {
+                            let date = date.format("%Y-%m-%d");
+                            rsx! { p { class: "text-sm text-gray-600", "{date}" } }
+                        }
+                        p {
+                            class: "whitespace-pre-line",
+                            "{review.comment}"
+                        }
+                    }
+                }
+            }
+        }
+        Some(Err(err)) => {
+            render! {
+                ShowError { context: "An error occurred while fetching reviews", error: err, onretry: move |_| reviews.restart() }
+            }
+        }
+        None => {
+            render! {
+                ShowLoading {}
             }
         }
     }
 }
 
+#[inline_props]
+fn ChartSpinPlays(cx: Scope, id: i32) -> Element {
+    let client = use_spinshare(cx);
+    let spinplays = use_future(cx, (id,), |(id,)| async move { client.get_spinplays(id).await });
+
+    match spinplays.value() {
+        Some(Ok(spinplays)) if spinplays.is_empty() => {
+            render! { "No SpinPlays yet" }
+        }
+        Some(Ok(spinplays)) => {
+            render! {
+                div {
+                    class: "grid grid-cols-2",
+                    for spinplay in spinplays {
+                        div {
+                            key: "{spinplay.id}",
+                            class: "rounded bg-gray-100 m-1 p-2",
+                            a {
+                                class: "text-blue-600 underline break-all",
+                                href: "{spinplay.video_url}",
+                                "{spinplay.video_url}"
+                            }
+                            UserShortDisplay { id: spinplay.user, label: "Player" }
+                        }
+                    }
+                }
+            }
+        }
+        Some(Err(err)) => {
+            render! {
+                ShowError { context: "An error occurred while fetching SpinPlays", error: err, onretry: move |_| spinplays.restart() }
+            }
+        }
+        None => {
+            render! {
+                ShowLoading {}
+            }
+        }
+    }
+}
+
+#[inline_props]
+fn DifficultyBadges<'a>(cx: Scope, difficulties: &'a ChartDifficulties) -> Element {
+    let badges = difficulties.available().into_iter().map(|d| {
+        let name = d.difficulty.name();
+        let class = format!("badge badge-{}", name.to_lowercase());
+        match d.rating {
+            Some(rating) => rsx! { span { class: "{class}", "{name} {rating}" } },
+            None => rsx! { span { class: "{class}", "{name}" } },
+        }
+    });
+    render! {
+        badges
+    }
+}
+
 #[inline_props]
 fn ChartShortDisplay<'a>(cx: Scope, chart: &'a PartialChart) -> Element {
     let PartialChart {
@@ -187,6 +1053,9 @@ fn ChartShortDisplay<'a>(cx: Scope, chart: &'a PartialChart) -> Element {
         artist,
         charter,
         cover,
+        is_explicit,
+        difficulties,
+        update_date,
         ..
     } = chart;
 
@@ -216,6 +1085,22 @@ fn ChartShortDisplay<'a>(cx: Scope, chart: &'a PartialChart) -> Element {
                             class: "text-gray-600",
                             "Charted by {charter}"
                         }
+                        div {
+                            class: "flex flex-wrap items-center",
+                            DifficultyBadges { difficulties: difficulties }
+                            if *is_explicit {
+                                rsx! { span { class: "badge badge-explicit", "Explicit" } }
+                            }
+                        }
+                        if let Some(update_date) = update_date {
+                            let age = format_age(update_date);
+                            rsx! {
+                                p {
+                                    class: "text-sm font-normal text-gray-600",
+                                    "Updated {age}"
+                                }
+                            }
+                        }
                     }
                 }
             }
@@ -223,60 +1108,231 @@ fn ChartShortDisplay<'a>(cx: Scope, chart: &'a PartialChart) -> Element {
     }
 }
 
+/// Formats how long ago a date was, e.g. "today" or "3 days ago".
+fn format_age(date: &DateTime<Utc>) -> String {
+    match (Utc::now() - *date).num_days() {
+        days if days <= 0 => "today".into(),
+        1 => "yesterday".into(),
+        days => format!("{days} days ago"),
+    }
+}
+
+/// Formats a count of things, e.g. "1 file" or "3 files".
+fn plural(count: usize, thing: &str) -> String {
+    match count {
+        1 => format!("1 {thing}"),
+        _ => format!("{count} {thing}s"),
+    }
+}
+
+/// Formats a byte count with a binary unit, e.g. "3.4 MB".
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{bytes} B"),
+        _ => format!("{size:.1} {}", UNITS[unit]),
+    }
+}
+
+/// Formats a short duration, e.g. "42s" or "3m 05s".
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0..=59 => format!("{secs}s"),
+        _ => format!("{}m {:02}s", secs / 60, secs % 60),
+    }
+}
+
+/// Home screen dashboard built from the SpinShare front page.
+pub fn FrontPageDisplay(cx: Scope) -> Element {
+    let client = use_spinshare(cx);
+    let front_page = use_future(cx, (), |_| async move { client.get_front_page().await });
+
+    match front_page.value() {
+        Some(Ok(front_page)) => {
+            let FrontPage {
+                promos,
+                staff_picks,
+                hot_this_week,
+                hot_this_month,
+                new_charts,
+            } = front_page;
+            render! {
+                div {
+                    class: "flex overflow-x-auto",
+                    for promo in promos {
+                        PromoDisplay { key: "{promo.id}", promo: promo }
+                    }
+                }
+                // SpinShare has no listing of staff picks beyond the ones on the front
+                // page, so there is nothing more to see.
+                ChartRow { title: "Staff picks", charts: staff_picks }
+                ChartRow { title: "Hot this week", charts: hot_this_week, see_all: Route::HotWeekCharts {} }
+                ChartRow { title: "Hot this month", charts: hot_this_month, see_all: Route::HotMonthCharts {} }
+                ChartRow { title: "New charts", charts: new_charts, see_all: Route::NewCharts {} }
+            }
+        }
+        Some(Err(err)) => {
+            render! {
+                ShowError { context: "An error occurred while fetching the front page", error: err, onretry: move |_| front_page.restart() }
+            }
+        }
+        None => {
+            render! {
+                ShowLoading {}
+            }
+        }
+    }
+}
+
 #[inline_props]
-pub fn ChartListing(cx: Scope, mode: ChartListingMode) -> Element {
-    let charts = match mode {
-        ChartListingMode::New(page) => use_future(cx, (page,), |_| get_new_charts(*page)),
-        ChartListingMode::Updated(page) => use_future(cx, (page,), |_| get_updated_charts(*page)),
-        ChartListingMode::HotWeek(page) => use_future(cx, (page,), |_| get_weekly_hot_charts(*page)),
-        ChartListingMode::HotMonth(page) => use_future(cx, (page,), |_| get_monthly_hot_charts(*page)),
-        ChartListingMode::User(id) => use_future(cx, (id,), |_| get_charts_for_user(*id)),
-        ChartListingMode::SearchChart(query, _) => use_future(cx, (query,), |_| search_chart(query.clone())),
+fn PromoDisplay<'a>(cx: Scope, promo: &'a Promo) -> Element {
+    let Promo {
+        title,
+        kind,
+        text_color,
+        color,
+        image,
+        button,
+        ..
+    } = promo;
+
+    let content = rsx! {
+        div {
+            class: "relative rounded-xl overflow-hidden w-96 h-48 m-1 p-4 bg-cover bg-center",
+            background_color: "{color}",
+            background_image: "url('{image}')",
+            color: "{text_color}",
+            p {
+                class: "text-sm uppercase",
+                "{kind}"
+            }
+            p {
+                class: "text-2xl font-bold",
+                "{title}"
+            }
+        }
+    };
+
+    let link = match button.target() {
+        Some(PromoTarget::Chart(id)) => rsx! { Link { to: Route::Chart { id }, content } },
+        Some(PromoTarget::Playlist(id)) => rsx! { Link { to: Route::Playlist { id }, content } },
+        Some(PromoTarget::Search(query)) => rsx! { Link { to: Route::Search { query }, content } },
+        Some(PromoTarget::Url(url)) => rsx! { a { href: "{url}", content } },
+        None => content,
     };
 
+    render! {
+        div {
+            class: "shrink-0",
+            link
+        }
+    }
+}
+
+/// Horizontally scrolling row of charts, with an optional link to the full listing.
+#[inline_props]
+fn ChartRow<'a>(cx: Scope, title: &'a str, charts: &'a Vec<PartialChart>, see_all: Option<Route>) -> Element {
+    if charts.is_empty() {
+        return None;
+    }
+
+    render! {
+        div {
+            class: "flex items-center mt-4",
+            h2 {
+                class: "text-2xl font-bold",
+                "{title}"
+            }
+            if let Some(route) = see_all {
+                rsx! {
+                    Link {
+                        class: "ml-auto text-blue-600",
+                        to: route.clone(),
+                        "See all >"
+                    }
+                }
+            }
+        }
+        div {
+            class: "flex overflow-x-auto",
+            for chart in charts.iter() {
+                div {
+                    key: "{chart.id}",
+                    class: "shrink-0 w-96",
+                    ChartShortDisplay { chart: chart }
+                }
+            }
+        }
+    }
+}
+
+#[inline_props]
+pub fn ChartListing(cx: Scope, mode: ChartListingMode) -> Element {
+    let client = use_spinshare(cx);
+    // The page is stored along with the listing it belongs to, so that a new search
+    // starts over from the first page.
+    let page = use_state(cx, || (mode.clone(), 0));
+    let current = if page.0 == *mode { page.1 } else { 0 };
+    let charts = use_future(cx, (mode, &current), |(mode, number)| async move {
+        match mode {
+            ChartListingMode::List(list) => client.get_chart_page(list, number).await,
+            ChartListingMode::User(id) => client.get_charts_for_user(id).await.map(|charts| Page::from_all(charts, number)),
+            ChartListingMode::SearchChart(query, filters) => client.search_chart(query, filters).await.map(|charts| Page::from_all(charts, number)),
+        }
+    });
+
     match charts.value() {
+        Some(Ok(charts)) if charts.items.is_empty() && charts.number == 0 => {
+            match mode {
+                ChartListingMode::SearchChart(query, _) => render! { "No charts found for {query}" },
+                _ => render! { "No charts found" },
+            }
+        }
         Some(Ok(charts)) => {
+            let number = charts.number + 1;
             render! {
-                div {
-                    class: "grid grid-cols-3",
-                    if let ChartListingMode::SearchChart(query, page) = mode {
-                        let cur_chart = (page * 12) as usize;
-                        let max_chart = std::cmp::min(((page + 1) * 12) as usize, charts.len());
-                        println!("{}, {}, {}, {:#?}", page, cur_chart, max_chart, charts);
-                        let charts = charts.get(cur_chart..max_chart);
-                        if let Some(charts) = charts {
-                            if charts.is_empty() {
-                                rsx! {
-                                    "No charts found for {query}"
-                                }
+                if charts.number > 0 || !charts.is_last {
+                    rsx! {
+                        div {
+                            class: "flex items-center",
+                            button {
+                                class: "btn btn-blue m-1",
+                                disabled: charts.number == 0,
+                                onclick: move |_| page.set((mode.clone(), current - 1)),
+                                "Previous Page"
                             }
-                            else {
-                                rsx! {
-                                    for chart in &charts {
-                                        ChartShortDisplay { chart: chart }
-                                    }
-                                }
+                            if let Some(count) = charts.page_count {
+                                rsx! { span { class: "m-1", "Page {number} of {count}" } }
+                            } else {
+                                rsx! { span { class: "m-1", "Page {number}" } }
                             }
-                        }
-                        else {
-                            rsx! {
-                                "Search out of bounds"
+                            button {
+                                class: "btn btn-blue m-1",
+                                disabled: charts.is_last,
+                                onclick: move |_| page.set((mode.clone(), current + 1)),
+                                "Next Page"
                             }
                         }
                     }
-                    else {
-                        rsx! {
-                            for chart in &charts {
-                                ChartShortDisplay { chart: chart }
-                            }
-                        }
+                }
+                div {
+                    class: "grid grid-cols-3",
+                    for chart in &charts.items {
+                        ChartShortDisplay { chart: chart }
                     }
                 }
             }
         }
         Some(Err(err)) => {
             render! {
-                "An error occurred while fetching charts: {err}"
+                ShowError { context: "An error occurred while fetching charts", error: err, onretry: move |_| charts.restart() }
             }
         }
         None => {
@@ -0,0 +1,36 @@
+use tokio::sync::watch;
+
+/// Whether SpinShare could be reached the last time it was asked, shared by every
+/// clone of the client so the UI can show a single status for the whole app.
+#[derive(Debug)]
+pub struct Connectivity {
+    online: watch::Sender<bool>,
+}
+
+impl Connectivity {
+    pub fn new() -> Self {
+        Self {
+            online: watch::channel(true).0,
+        }
+    }
+
+    pub fn is_online(&self) -> bool {
+        *self.online.borrow()
+    }
+
+    /// Records the outcome of a request. Returns whether this changed the status.
+    pub fn set_online(&self, online: bool) -> bool {
+        self.online.send_if_modified(|current| std::mem::replace(current, online) != online)
+    }
+
+    /// Returns a receiver notified whenever the status changes.
+    pub fn subscribe(&self) -> watch::Receiver<bool> {
+        self.online.subscribe()
+    }
+}
+
+impl Default for Connectivity {
+    fn default() -> Self {
+        Self::new()
+    }
+}
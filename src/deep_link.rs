@@ -0,0 +1,115 @@
+//! `spinshare-song://` links opened from the SpinShare website, and handing them over to
+//! an already running window so that only one instance of the app is open.
+
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+
+use crate::download::cache_dir;
+use crate::route::Route;
+
+const SONG_SCHEME: &str = "spinshare-song://";
+
+/// Sent back by the running instance, so that a stale port file pointing at some other
+/// program isn't mistaken for it.
+const HANDSHAKE: &[u8] = b"spinexus\n";
+
+const FORWARD_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeepLink {
+    Chart(i32),
+    /// The website links charts by file reference rather than by ID.
+    ChartByReference(String),
+}
+
+impl DeepLink {
+    pub fn parse(uri: &str) -> Option<Self> {
+        let target = uri.strip_prefix(SONG_SCHEME)?.trim_end_matches('/');
+        if target.is_empty() {
+            return None;
+        }
+        Some(match target.parse() {
+            Ok(id) => DeepLink::Chart(id),
+            Err(_) => DeepLink::ChartByReference(target.into()),
+        })
+    }
+
+    pub fn route(&self) -> Route {
+        match self {
+            DeepLink::Chart(id) => Route::Chart { id: *id },
+            DeepLink::ChartByReference(reference) => Route::ChartByReference { reference: reference.clone() },
+        }
+    }
+}
+
+/// Links received from later launches of the app, taken by the router layout once it
+/// is mounted. `None` asks the window to come to the front without navigating.
+pub struct IncomingLinks(pub Option<UnboundedReceiver<Option<DeepLink>>>);
+
+fn port_file() -> PathBuf {
+    PathBuf::from(cache_dir()).join("instance.port")
+}
+
+/// Passes the command-line argument, if any, on to an already running instance.
+/// Returns whether there was one to take it.
+pub fn forward_to_running_instance(arg: Option<&str>) -> bool {
+    let port = fs::read_to_string(port_file()).ok().and_then(|port| port.trim().parse::<u16>().ok());
+    let Some(port) = port else {
+        return false;
+    };
+    let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+    let forward = || -> io::Result<bool> {
+        let mut stream = TcpStream::connect_timeout(&addr, FORWARD_TIMEOUT)?;
+        stream.set_read_timeout(Some(FORWARD_TIMEOUT))?;
+        writeln!(stream, "{}", arg.unwrap_or_default())?;
+        let mut reply = [0; HANDSHAKE.len()];
+        stream.read_exact(&mut reply)?;
+        Ok(reply == HANDSHAKE)
+    };
+    forward().unwrap_or(false)
+}
+
+/// Starts accepting links from later launches, and records where to find this instance.
+pub fn listen() -> io::Result<UnboundedReceiver<Option<DeepLink>>> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+    fs::create_dir_all(cache_dir())?;
+    fs::write(port_file(), listener.local_addr()?.port().to_string())?;
+
+    let (sender, receiver) = mpsc::unbounded_channel();
+    thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let mut line = String::new();
+            let _ = stream.set_read_timeout(Some(FORWARD_TIMEOUT));
+            if BufReader::new(&stream).read_line(&mut line).is_err() {
+                continue;
+            }
+            let _ = stream.write_all(HANDSHAKE);
+            if sender.send(DeepLink::parse(line.trim())).is_err() {
+                break;
+            }
+        }
+    });
+    Ok(receiver)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_song_links() {
+        assert_eq!(DeepLink::parse("spinshare-song://1234"), Some(DeepLink::Chart(1234)));
+        assert_eq!(
+            DeepLink::parse("spinshare-song://spinshare_5f1a2b/"),
+            Some(DeepLink::ChartByReference("spinshare_5f1a2b".into()))
+        );
+        assert_eq!(DeepLink::parse("spinshare-song://"), None);
+        assert_eq!(DeepLink::parse("https://spinsha.re/song/1234"), None);
+    }
+}
@@ -1,83 +1,429 @@
-use std::fs::{File, self};
+use std::fs::{File, OpenOptions, self};
 use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::io;
-use std::io::Write;
+use std::io::{Seek, SeekFrom, Write};
+use std::time::{Duration, Instant};
 use futures_util::StreamExt;
+use reqwest::StatusCode;
+use reqwest::header::{CONTENT_RANGE, ETAG};
+use serde::{Serialize, Deserialize};
+use tokio::sync::Notify;
 
-async fn download_file_internal(url: &str, path: &str) -> Result<(), String> {
-    let res = reqwest::get(url)
-        .await
-        .or(Err(format!("Failed to get content at {url}")))?;
+use crate::api::SpinShareClient;
+use crate::error::SpinexusError;
 
-    let mut file = File::create(path)
-        .or(Err(format!("Failed to create file at {path}")))?;
+/// How often progress is reported while downloading, so that fast connections don't
+/// flood the UI with updates.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
 
-    let mut stream = res.bytes_stream();
+/// How many times a download cut off halfway is resumed before giving up.
+const RESUME_ATTEMPTS: u32 = 3;
+
+/// Tells a download or extraction to stop. Clones share the same state.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<CancelState>);
+
+#[derive(Debug, Default)]
+struct CancelState {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
 
-    while let Some(item) = stream.next().await {
-        let chunk = item.or(Err("Error while downloading file"))?;
-        file.write_all(&chunk)
-            .or(Err("Error while writing file"))?;
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
     }
 
-    Ok(())
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::SeqCst);
+        self.0.notify.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Completes once the token is cancelled.
+    pub async fn cancelled(&self) {
+        loop {
+            // Registered before checking the flag, so a cancel in between isn't missed.
+            let notified = self.0.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    pub fn check(&self) -> Result<(), SpinexusError> {
+        match self.is_cancelled() {
+            true => Err(SpinexusError::Cancelled),
+            false => Ok(()),
+        }
+    }
 }
 
-async fn decompress_zip(zip: &str, destination: String) -> io::Result<()> {
-    let file = File::open(zip)?;
-    let mut archive = zip::ZipArchive::new(file)?;
+/// Stored next to a partial download, so that it can be resumed later instead of
+/// starting over.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct PartialDownload {
+    url: String,
+    etag: String,
+    bytes: u64,
+}
 
-    let dest = Path::new(destination.as_str());
+impl PartialDownload {
+    fn sidecar_path(path: &str) -> String {
+        format!("{path}.partial.json")
+    }
 
-    for i in 0..archive.len() {
-        let mut file = archive.by_index(i)?;
-        let out_path = match file.enclosed_name() {
-            Some(path) => dest.join(path),
-            None => continue,
-        };
+    /// Reads the resume state for `path`, if it belongs to `url` and the file on disk
+    /// holds at least as many bytes as recorded.
+    fn load(path: &str, url: &str) -> Option<Self> {
+        let partial: Self = serde_json::from_slice(&fs::read(Self::sidecar_path(path)).ok()?).ok()?;
+        let len = fs::metadata(path).ok()?.len();
+        (partial.url == url && partial.bytes > 0 && len >= partial.bytes).then_some(partial)
+    }
 
-        if (*file.name()).ends_with('/') {
-            fs::create_dir_all(&out_path)?;
-        } else {
-            if let Some(p) = out_path.parent() {
-                if !p.exists() {
-                    fs::create_dir_all(p)?;
-                }
+    fn save(&self, path: &str) -> io::Result<()> {
+        fs::write(Self::sidecar_path(path), serde_json::to_vec(self)?)
+    }
+
+    fn remove(path: &str) {
+        // A leftover sidecar is ignored once its file is gone, so failing here is harmless.
+        let _ = fs::remove_file(Self::sidecar_path(path));
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DownloadProgress {
+    pub received: u64,
+    /// Size announced by the server in `Content-Length`, if any.
+    pub total: Option<u64>,
+    /// Smoothed transfer rate.
+    pub bytes_per_sec: f64,
+}
+
+impl DownloadProgress {
+    pub fn fraction(&self) -> Option<f64> {
+        self.total.filter(|total| *total > 0).map(|total| self.received as f64 / total as f64)
+    }
+
+    pub fn eta(&self) -> Option<Duration> {
+        let remaining = self.total?.saturating_sub(self.received);
+        (self.bytes_per_sec > 0.0).then(|| Duration::from_secs_f64(remaining as f64 / self.bytes_per_sec))
+    }
+}
+
+async fn download_file_internal(client: &SpinShareClient, url: &str, path: &str, cancel: &CancelToken, on_progress: &mut impl FnMut(DownloadProgress)) -> Result<(), SpinexusError> {
+    cancel.check()?;
+    let mut partial = PartialDownload::load(path, url);
+    let mut res = match &partial {
+        Some(partial) => client.get_file_range(url, partial.bytes, &partial.etag).await?,
+        None => client.get_file(url).await?,
+    };
+    if res.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+        partial = None;
+        res = client.get_file(url).await?;
+    }
+    let mut res = res.error_for_status()?;
+
+    let resume_from = match partial {
+        Some(partial) if res.status() == StatusCode::PARTIAL_CONTENT && content_range_start(&res) == Some(partial.bytes) => partial.bytes,
+        // Servers that ignore ranges, or have a different file under that ETag now, send
+        // everything again with a 200.
+        _ if res.status() == StatusCode::OK => 0,
+        // Any other part of the file can't be appended to what we have, so start over.
+        _ => {
+            PartialDownload::remove(path);
+            res = client.get_file(url).await?;
+            if res.status() != StatusCode::OK {
+                return Err(SpinexusError::HttpStatus { url: url.into(), status: res.status() });
             }
-            let mut out_file = File::create(&out_path)?;
-            io::copy(&mut file, &mut out_file)?;
+            0
         }
+    };
+    let mut file = if resume_from > 0 {
+        let mut file = OpenOptions::new().write(true).open(path)?;
+        file.set_len(resume_from)?;
+        file.seek(SeekFrom::End(0))?;
+        file
+    } else {
+        PartialDownload::remove(path);
+        File::create(path)?
+    };
+
+    // Weak ETags can't be used to resume.
+    let etag = res.headers().get(ETAG)
+        .and_then(|etag| etag.to_str().ok())
+        .filter(|etag| !etag.starts_with("W/"))
+        .map(String::from);
+    let mut sidecar = etag.map(|etag| PartialDownload {
+        url: url.into(),
+        etag,
+        bytes: resume_from,
+    });
+
+    let mut progress = DownloadProgress {
+        received: resume_from,
+        total: res.content_length().map(|len| len + resume_from),
+        ..DownloadProgress::default()
+    };
+    let mut reported_at = Instant::now();
+    let mut reported_bytes = resume_from;
+    on_progress(progress.clone());
 
-        #[cfg(unix)]
-        {
-            use std::os::unix::fs::PermissionsExt;
+    let mut stream = res.bytes_stream();
 
-            if let Some(mode) = file.unix_mode() {
-                fs::set_permissions(&out_path, fs::Permissions::from_mode(mode))?;
+    loop {
+        let next = tokio::select! {
+            biased;
+            _ = cancel.cancelled() => return Err(SpinexusError::Cancelled),
+            next = stream.next() => next,
+        };
+        let Some(item) = next else {
+            break;
+        };
+        let chunk = match item {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                if let Some(sidecar) = &mut sidecar {
+                    sidecar.bytes = progress.received;
+                    let _ = sidecar.save(path);
+                }
+                return Err(e.into());
             }
+        };
+        file.write_all(&chunk)?;
+        progress.received += chunk.len() as u64;
+
+        let elapsed = reported_at.elapsed();
+        if elapsed >= PROGRESS_INTERVAL {
+            let rate = (progress.received - reported_bytes) as f64 / elapsed.as_secs_f64();
+            progress.bytes_per_sec = match progress.bytes_per_sec {
+                0.0 => rate,
+                previous => previous * 0.7 + rate * 0.3,
+            };
+            reported_at = Instant::now();
+            reported_bytes = progress.received;
+            if let Some(sidecar) = &mut sidecar {
+                sidecar.bytes = progress.received;
+                sidecar.save(path)?;
+            }
+            on_progress(progress.clone());
         }
     }
+    on_progress(progress);
 
     Ok(())
 }
 
-pub async fn download_file(url: String, path: String) -> Result<(), String> {
-    match download_file_internal(url.as_str(), path.as_str()).await {
-        Ok(_) => Ok(()),
-        Err(e) => {
-            if Path::new(&path).exists() {
-                fs::remove_file(&path)
-                    .or(Err(format!("Failed file cleanup after error: {e}")))?;
+/// Returns where the body of a 206 response starts, from `Content-Range: bytes 100-199/200`.
+fn content_range_start(res: &reqwest::Response) -> Option<u64> {
+    let range = res.headers().get(CONTENT_RANGE)?.to_str().ok()?;
+    let (start, _) = range.strip_prefix("bytes ")?.split_once('-')?;
+    start.trim().parse().ok()
+}
+
+/// Downloads `url` to `path`.
+///
+/// When the connection drops halfway and the server gave the file an ETag, the partial
+/// file is kept and the download resumes from where it stopped, both right away and on
+/// later calls for the same file. Cancelling throws the partial file away.
+pub async fn download_file(client: &SpinShareClient, url: String, path: String, cancel: &CancelToken, mut on_progress: impl FnMut(DownloadProgress)) -> Result<(), SpinexusError> {
+    let mut attempt = 0;
+    loop {
+        let e = match download_file_internal(client, url.as_str(), path.as_str(), cancel, &mut on_progress).await {
+            Ok(_) => {
+                PartialDownload::remove(&path);
+                return Ok(());
             }
-            Err(e)
+            Err(e) => e,
+        };
+        if let SpinexusError::Cancelled = e {
+            PartialDownload::remove(&path);
+        }
+        let resumable = PartialDownload::load(&path, &url).is_some();
+        if resumable && matches!(e, SpinexusError::Network(_)) && attempt < RESUME_ATTEMPTS {
+            attempt += 1;
+            continue;
         }
+        if !resumable && Path::new(&path).exists() {
+            // The download error is more useful to report than a failed cleanup.
+            let _ = fs::remove_file(&path);
+        }
+        return Err(e);
     }
 }
 
-pub async fn download_and_extract_zip(url: String, cache: String, destination: String, filename: String) -> Result<(), String> {
-    let cached_zip = Path::new(cache.as_str()).join(filename.as_str()).to_str().unwrap().to_string();
-    download_file(url, cached_zip.clone()).await?;
-    decompress_zip(cached_zip.as_str(), destination).await.or(Err("Could not extract zip"))?;
-    fs::remove_file(&cached_zip).or(Err("Failed file cleanup"))?;
-    Ok(())
+/// Directory where zips are kept while they are being downloaded and extracted.
+pub fn cache_dir() -> String {
+    let proj_dir = directories::ProjectDirs::from("rs", "", "spinexus").unwrap();
+    proj_dir.cache_dir().to_str().unwrap().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::retry::RetryPolicy;
+    use crate::test_server::{MockResponse, MockServer, RecordedRequest, TempDir};
+
+    const ETAG: &str = "\"v1\"";
+
+    fn file_body() -> Vec<u8> {
+        (0..64 * 1024).map(|i| (i % 251) as u8).collect()
+    }
+
+    /// Serves `file_body()` with ranges, the way a well-behaved file server does.
+    fn serve_range(req: &RecordedRequest) -> MockResponse {
+        let body = file_body();
+        let start = req.header("range")
+            .filter(|_| req.header("if-range") == Some(ETAG))
+            .and_then(|range| range.strip_prefix("bytes=")?.strip_suffix('-')?.parse::<usize>().ok());
+        match start {
+            Some(start) => MockResponse::new(206, body[start..].to_vec())
+                .header("ETag", ETAG)
+                .header("Content-Range", &format!("bytes {start}-{}/{}", body.len() - 1, body.len())),
+            None => MockResponse::new(200, body).header("ETag", ETAG),
+        }
+    }
+
+    /// Sends the first half of the file but announces all of it, as if the connection
+    /// dropped halfway.
+    fn cut_off(response: MockResponse) -> MockResponse {
+        let len = response.body.len();
+        let mut response = response.header("Content-Length", &len.to_string());
+        response.body.truncate(len / 2);
+        response
+    }
+
+    fn client() -> SpinShareClient {
+        SpinShareClient::builder()
+            .retry_policy(RetryPolicy { max_retries: 0, ..RetryPolicy::default() })
+            .build()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn resumes_interrupted_download() {
+        let server = MockServer::start(|i, req| match i {
+            0 => cut_off(serve_range(req)),
+            _ => serve_range(req),
+        });
+        let dir = TempDir::new();
+        let path = dir.file("resume.zip");
+        let url = format!("{}/file.zip", server.url);
+        download_file(&client(), url, path.clone(), &CancelToken::new(), |_| {}).await.unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), file_body());
+        assert!(!Path::new(&PartialDownload::sidecar_path(&path)).exists());
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1].header("range"), Some(format!("bytes={}-", file_body().len() / 2).as_str()));
+        assert_eq!(requests[1].header("if-range"), Some(ETAG));
+    }
+
+    #[tokio::test]
+    async fn resumes_partial_download_from_earlier_run() {
+        let server = MockServer::start(|_, req| serve_range(req));
+        let dir = TempDir::new();
+        let path = dir.file("earlier.zip");
+        let url = format!("{}/file.zip", server.url);
+        fs::write(&path, &file_body()[..1000]).unwrap();
+        PartialDownload { url: url.clone(), etag: ETAG.into(), bytes: 1000 }.save(&path).unwrap();
+
+        let mut reported = vec![];
+        download_file(&client(), url, path.clone(), &CancelToken::new(), |p| reported.push(p)).await.unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), file_body());
+        assert_eq!(server.requests()[0].header("range"), Some("bytes=1000-"));
+        assert_eq!(reported[0].received, 1000);
+        assert_eq!(reported.last().unwrap().total, Some(file_body().len() as u64));
+    }
+
+    #[tokio::test]
+    async fn starts_over_when_etag_changed() {
+        let server = MockServer::start(|_, req| serve_range(req));
+        let dir = TempDir::new();
+        let path = dir.file("changed.zip");
+        let url = format!("{}/file.zip", server.url);
+        fs::write(&path, vec![0xff; 1000]).unwrap();
+        PartialDownload { url: url.clone(), etag: "\"v0\"".into(), bytes: 1000 }.save(&path).unwrap();
+
+        download_file(&client(), url, path.clone(), &CancelToken::new(), |_| {}).await.unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), file_body());
+        assert_eq!(server.requests()[0].header("if-range"), Some("\"v0\""));
+    }
+
+    #[tokio::test]
+    async fn starts_over_when_ranges_are_ignored() {
+        let server = MockServer::start(|_, _| MockResponse::new(200, file_body()).header("ETag", ETAG));
+        let dir = TempDir::new();
+        let path = dir.file("ignored.zip");
+        let url = format!("{}/file.zip", server.url);
+        fs::write(&path, vec![0xff; 1000]).unwrap();
+        PartialDownload { url: url.clone(), etag: ETAG.into(), bytes: 1000 }.save(&path).unwrap();
+
+        download_file(&client(), url, path.clone(), &CancelToken::new(), |_| {}).await.unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), file_body());
+    }
+
+    #[tokio::test]
+    async fn starts_over_when_range_is_misaligned() {
+        // Answers the range request with a slice starting elsewhere than asked.
+        let server = MockServer::start(|_, req| match req.header("range") {
+            Some(_) => {
+                let body = file_body();
+                MockResponse::new(206, body[500..].to_vec())
+                    .header("ETag", ETAG)
+                    .header("Content-Range", &format!("bytes 500-{}/{}", body.len() - 1, body.len()))
+            }
+            None => serve_range(req),
+        });
+        let dir = TempDir::new();
+        let path = dir.file("misaligned.zip");
+        let url = format!("{}/file.zip", server.url);
+        fs::write(&path, &file_body()[..1000]).unwrap();
+        PartialDownload { url: url.clone(), etag: ETAG.into(), bytes: 1000 }.save(&path).unwrap();
+
+        download_file(&client(), url, path.clone(), &CancelToken::new(), |_| {}).await.unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), file_body());
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1].header("range"), None);
+    }
+
+    #[tokio::test]
+    async fn cancelling_removes_partial_download() {
+        let server = MockServer::start(|_, req| serve_range(req));
+        let dir = TempDir::new();
+        let path = dir.file("cancelled.zip");
+        let url = format!("{}/file.zip", server.url);
+        fs::write(&path, &file_body()[..1000]).unwrap();
+        PartialDownload { url: url.clone(), etag: ETAG.into(), bytes: 1000 }.save(&path).unwrap();
+
+        let cancel = CancelToken::new();
+        let res = download_file(&client(), url, path.clone(), &cancel, |_| cancel.cancel()).await;
+
+        assert!(matches!(res, Err(SpinexusError::Cancelled)));
+        assert!(!Path::new(&path).exists());
+        assert!(!Path::new(&PartialDownload::sidecar_path(&path)).exists());
+    }
+
+    #[tokio::test]
+    async fn discards_partial_download_without_etag() {
+        let server = MockServer::start(|_, _| cut_off(MockResponse::new(200, file_body())));
+        let dir = TempDir::new();
+        let path = dir.file("no-etag.zip");
+        let url = format!("{}/file.zip", server.url);
+
+        assert!(download_file(&client(), url, path.clone(), &CancelToken::new(), |_| {}).await.is_err());
+        assert!(!Path::new(&path).exists());
+        assert_eq!(server.requests().len(), 1);
+    }
 }
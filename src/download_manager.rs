@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::{oneshot, watch};
+
+use crate::api::SpinShareClient;
+use crate::app_config::AppConfig;
+use crate::download::{cache_dir, download_file, CancelToken, DownloadProgress};
+use crate::install::{decompress_zip, ArchiveLimits, ConflictPolicy, ConflictResolution, ExtractProgress, InstallReport};
+use crate::error::SpinexusError;
+
+pub type JobId = u64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobState {
+    Queued,
+    Downloading(DownloadProgress),
+    Extracting(ExtractProgress),
+    /// Waiting for the user to decide what to do with these files, which already exist
+    /// with different contents.
+    AwaitingDecision(Vec<PathBuf>),
+    Done(InstallReport),
+    Failed(String),
+    Cancelled,
+}
+
+impl JobState {
+    pub fn is_finished(&self) -> bool {
+        matches!(self, JobState::Done(_) | JobState::Failed(_) | JobState::Cancelled)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DownloadJob {
+    pub id: JobId,
+    pub chart_id: i32,
+    pub title: String,
+    /// Customs folder the chart is installed into.
+    pub destination: String,
+    pub state: JobState,
+}
+
+/// Queue of chart downloads, shared by the whole app.
+///
+/// Jobs run on the async runtime rather than in a component, so they carry on when the
+/// page that started them is left. At most `max_concurrent` run at once, the rest wait
+/// in the order they were added.
+#[derive(Clone)]
+pub struct DownloadManager {
+    client: SpinShareClient,
+    limits: ArchiveLimits,
+    inner: Arc<Mutex<Inner>>,
+    changes: Arc<watch::Sender<()>>,
+}
+
+struct Inner {
+    jobs: Vec<DownloadJob>,
+    /// Tokens of the jobs currently running.
+    running_tokens: HashMap<JobId, CancelToken>,
+    /// Where to send the user's decision for jobs awaiting one.
+    decisions: HashMap<JobId, oneshot::Sender<ConflictResolution>>,
+    next_id: JobId,
+    running: usize,
+    max_concurrent: usize,
+    conflict_policy: ConflictPolicy,
+}
+
+impl DownloadManager {
+    pub fn from_config(client: SpinShareClient, config: &AppConfig) -> Self {
+        Self {
+            client,
+            limits: config.archive_limits.clone(),
+            inner: Arc::new(Mutex::new(Inner {
+                jobs: vec![],
+                running_tokens: HashMap::new(),
+                decisions: HashMap::new(),
+                next_id: 0,
+                running: 0,
+                max_concurrent: config.max_concurrent_downloads.max(1),
+                conflict_policy: config.conflict_policy,
+            })),
+            changes: Arc::new(watch::channel(()).0),
+        }
+    }
+
+    /// Queues a chart for download, unless it is already queued or downloading, and
+    /// returns the job taking care of it.
+    pub fn enqueue(&self, chart_id: i32, title: String, destination: String) -> JobId {
+        let id = {
+            let mut inner = self.inner.lock().unwrap();
+            let pending = inner.jobs.iter().find(|job| job.chart_id == chart_id && !job.state.is_finished());
+            if let Some(job) = pending {
+                return job.id;
+            }
+            let id = inner.next_id;
+            inner.next_id += 1;
+            inner.jobs.push(DownloadJob {
+                id,
+                chart_id,
+                title,
+                destination,
+                state: JobState::Queued,
+            });
+            id
+        };
+        self.changes.send_replace(());
+        self.start_queued();
+        id
+    }
+
+    pub fn jobs(&self) -> Vec<DownloadJob> {
+        self.inner.lock().unwrap().jobs.clone()
+    }
+
+    /// Returns the most recent job for a chart.
+    pub fn job_for_chart(&self, chart_id: i32) -> Option<DownloadJob> {
+        self.inner.lock().unwrap().jobs.iter().rev().find(|job| job.chart_id == chart_id).cloned()
+    }
+
+    pub fn set_max_concurrent(&self, max_concurrent: usize) {
+        self.inner.lock().unwrap().max_concurrent = max_concurrent.max(1);
+        self.start_queued();
+    }
+
+    /// Sets how conflicting files are handled, for jobs that didn't get to installing yet.
+    pub fn set_conflict_policy(&self, policy: ConflictPolicy) {
+        self.inner.lock().unwrap().conflict_policy = policy;
+    }
+
+    /// Answers a job awaiting a decision about conflicting files.
+    pub fn resolve_conflicts(&self, id: JobId, resolution: ConflictResolution) {
+        if let Some(decision) = self.inner.lock().unwrap().decisions.remove(&id) {
+            let _ = decision.send(resolution);
+        }
+    }
+
+    /// Stops a job. Queued jobs are dropped from the queue, running ones stop as soon as
+    /// possible and clean up after themselves.
+    pub fn cancel(&self, id: JobId) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(token) = inner.running_tokens.get(&id) {
+            token.cancel();
+            return;
+        }
+        if let Some(job) = inner.jobs.iter_mut().find(|job| job.id == id && job.state == JobState::Queued) {
+            job.state = JobState::Cancelled;
+        }
+        drop(inner);
+        self.changes.send_replace(());
+    }
+
+    /// Forgets about jobs that are done, failed or cancelled.
+    pub fn clear_finished(&self) {
+        self.inner.lock().unwrap().jobs.retain(|job| !job.state.is_finished());
+        self.changes.send_replace(());
+    }
+
+    /// Returns a receiver notified whenever a job is added, removed or changes state.
+    pub fn subscribe(&self) -> watch::Receiver<()> {
+        self.changes.subscribe()
+    }
+
+    fn set_state(&self, id: JobId, state: JobState) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(job) = inner.jobs.iter_mut().find(|job| job.id == id) {
+            job.state = state;
+        }
+        drop(inner);
+        self.changes.send_replace(());
+    }
+
+    /// Starts queued jobs until the concurrency limit is reached.
+    fn start_queued(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        while inner.running < inner.max_concurrent {
+            let Some(job) = inner.jobs.iter_mut().find(|job| job.state == JobState::Queued) else {
+                break;
+            };
+            job.state = JobState::Downloading(DownloadProgress::default());
+            let job = job.clone();
+            let cancel = CancelToken::new();
+            inner.running_tokens.insert(job.id, cancel.clone());
+            inner.running += 1;
+
+            let manager = self.clone();
+            tokio::spawn(async move {
+                // Run on its own, so that the job's slot is given back even if it panics.
+                let task = tokio::spawn({
+                    let (manager, job, cancel) = (manager.clone(), job.clone(), cancel.clone());
+                    async move { manager.run(&job, &cancel).await }
+                });
+                let state = match task.await {
+                    Ok(Ok(report)) => JobState::Done(report),
+                    Ok(Err(SpinexusError::Cancelled)) => JobState::Cancelled,
+                    Ok(Err(e)) => JobState::Failed(e.to_string()),
+                    Err(_) => JobState::Failed("The download stopped unexpectedly".into()),
+                };
+                let mut inner = manager.inner.lock().unwrap();
+                inner.running_tokens.remove(&job.id);
+                inner.decisions.remove(&job.id);
+                inner.running -= 1;
+                drop(inner);
+                manager.set_state(job.id, state);
+                manager.start_queued();
+            });
+        }
+        drop(inner);
+        self.changes.send_replace(());
+    }
+
+    async fn run(&self, job: &DownloadJob, cancel: &CancelToken) -> Result<InstallReport, SpinexusError> {
+        let chart = self.client.get_chart(job.chart_id).await?;
+        cancel.check()?;
+        let cache = cache_dir();
+        fs::create_dir_all(&cache)?;
+        // Named after the ID rather than the file reference, which comes from the server
+        // and could point anywhere.
+        let zip = Path::new(&cache).join(format!("{}.zip", chart.id));
+        let zip = zip.to_str().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "the cache folder path isn't valid UTF-8"))?.to_string();
+        download_file(&self.client, chart.paths.zip, zip.clone(), cancel, |progress| {
+            self.set_state(job.id, JobState::Downloading(progress));
+        }).await?;
+        let policy = self.inner.lock().unwrap().conflict_policy;
+        let ask = |conflicts| {
+            let (decision, answer) = oneshot::channel();
+            self.inner.lock().unwrap().decisions.insert(job.id, decision);
+            self.set_state(job.id, JobState::AwaitingDecision(conflicts));
+            async move { answer.await.map_err(|_| SpinexusError::Cancelled) }
+        };
+        let installed = decompress_zip(&zip, job.destination.clone(), &self.limits, policy, ask, cancel, |progress| {
+            self.set_state(job.id, JobState::Extracting(progress));
+        }).await;
+        // The zip is only kept to resume the download, so it goes whatever happened.
+        let removed = fs::remove_file(&zip);
+        let report = installed?;
+        removed?;
+        Ok(report)
+    }
+}
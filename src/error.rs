@@ -0,0 +1,124 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+
+use reqwest::StatusCode;
+use zip::result::ZipError;
+
+/// Everything that can go wrong while talking to SpinShare or installing a chart.
+///
+/// Sources are reference counted so errors can be cloned into UI state.
+#[derive(Debug, Clone)]
+pub enum SpinexusError {
+    /// The request could not be sent or the response could not be read.
+    Network(Arc<reqwest::Error>),
+    /// The server answered with a non-success HTTP status.
+    HttpStatus { url: String, status: StatusCode },
+    /// The response body didn't match the expected JSON.
+    Decode(Arc<serde_json::Error>),
+    /// SpinShare reported that the requested resource doesn't exist.
+    NotFound,
+    /// SpinShare refused to serve the requested resource.
+    Forbidden,
+    /// SpinShare reported an internal error.
+    ServerError { status: i32 },
+    /// SpinShare answered with any other non-success status inside its response envelope.
+    Api { status: i32 },
+    Io(Arc<io::Error>),
+    Zip(Arc<ZipError>),
+    /// A chart archive was refused before anything was installed from it.
+    InvalidArchive(String),
+    /// The user stopped the operation.
+    Cancelled,
+}
+
+impl SpinexusError {
+    /// Maps a non-success status from the SpinShare response envelope to an error.
+    pub fn from_api_status(status: i32) -> Self {
+        match status {
+            404 => SpinexusError::NotFound,
+            403 => SpinexusError::Forbidden,
+            500..=599 => SpinexusError::ServerError { status },
+            status => SpinexusError::Api { status },
+        }
+    }
+
+    /// Whether trying the same operation again has a chance of succeeding.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            SpinexusError::Network(err) => err.is_timeout() || err.is_connect() || err.is_request() || err.is_body(),
+            SpinexusError::HttpStatus { status, .. } => status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS,
+            SpinexusError::ServerError { .. } => true,
+            SpinexusError::NotFound | SpinexusError::Forbidden | SpinexusError::Api { .. } => false,
+            SpinexusError::Decode(_) | SpinexusError::Io(_) | SpinexusError::Zip(_) | SpinexusError::InvalidArchive(_) | SpinexusError::Cancelled => false,
+        }
+    }
+}
+
+impl fmt::Display for SpinexusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpinexusError::Network(err) if err.is_timeout() => write!(f, "SpinShare took too long to respond"),
+            SpinexusError::Network(_) => write!(f, "Could not reach SpinShare"),
+            SpinexusError::HttpStatus { url, status } => write!(f, "Request to {url} failed with HTTP status {status}"),
+            SpinexusError::Decode(_) => write!(f, "SpinShare sent a response that couldn't be read"),
+            SpinexusError::NotFound => write!(f, "This doesn't exist on SpinShare"),
+            SpinexusError::Forbidden => write!(f, "SpinShare denied access to this"),
+            SpinexusError::ServerError { status } => write!(f, "SpinShare ran into an error (status {status})"),
+            SpinexusError::Api { status } => write!(f, "SpinShare returned status {status}"),
+            SpinexusError::Io(err) => write!(f, "File system error: {err}"),
+            SpinexusError::Zip(err) => write!(f, "Could not extract zip: {err}"),
+            SpinexusError::InvalidArchive(reason) => write!(f, "Chart archive rejected: {reason}"),
+            SpinexusError::Cancelled => write!(f, "Cancelled"),
+        }
+    }
+}
+
+impl Error for SpinexusError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SpinexusError::Network(err) => Some(err.as_ref()),
+            SpinexusError::Decode(err) => Some(err.as_ref()),
+            SpinexusError::Io(err) => Some(err.as_ref()),
+            SpinexusError::Zip(err) => Some(err.as_ref()),
+            SpinexusError::HttpStatus { .. }
+            | SpinexusError::NotFound
+            | SpinexusError::Forbidden
+            | SpinexusError::ServerError { .. }
+            | SpinexusError::Api { .. }
+            | SpinexusError::InvalidArchive(_)
+            | SpinexusError::Cancelled => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for SpinexusError {
+    fn from(err: reqwest::Error) -> Self {
+        match (err.status(), err.url()) {
+            (Some(status), Some(url)) => SpinexusError::HttpStatus { url: url.to_string(), status },
+            _ => SpinexusError::Network(Arc::new(err)),
+        }
+    }
+}
+
+impl From<serde_json::Error> for SpinexusError {
+    fn from(err: serde_json::Error) -> Self {
+        SpinexusError::Decode(Arc::new(err))
+    }
+}
+
+impl From<io::Error> for SpinexusError {
+    fn from(err: io::Error) -> Self {
+        SpinexusError::Io(Arc::new(err))
+    }
+}
+
+impl From<ZipError> for SpinexusError {
+    fn from(err: ZipError) -> Self {
+        match err {
+            ZipError::Io(err) => err.into(),
+            err => SpinexusError::Zip(Arc::new(err)),
+        }
+    }
+}
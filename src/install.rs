@@ -0,0 +1,834 @@
+use std::fs::{self, File};
+use std::future::Future;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+use zip::read::ZipFile;
+use zip::ZipArchive;
+
+use crate::download::CancelToken;
+use crate::error::SpinexusError;
+
+/// Entries smaller than this aren't checked against `max_compression_ratio`, as tiny
+/// files can compress extremely well without being of any danger.
+const RATIO_CHECK_MIN_SIZE: u64 = 1024 * 1024;
+
+/// Held from planning until the files are in place, so that installations running at
+/// the same time don't plan against files another one is about to move in.
+static INSTALLING: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// File type bits of a unix mode.
+const S_IFMT: u32 = 0o170000;
+const S_IFREG: u32 = 0o100000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFLNK: u32 = 0o120000;
+
+/// What a chart archive may contain. Charts are uploaded by anyone, so archives are
+/// checked against these before anything is extracted.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ArchiveLimits {
+    /// Size of all entries once extracted, in bytes.
+    pub max_total_size: u64,
+    pub max_entries: usize,
+    /// Ratio between the extracted and compressed size of a single entry.
+    pub max_compression_ratio: u64,
+    /// Permission bits kept from the archive for extracted files, `0o644` (420) by
+    /// default to drop executable, setuid and setgid bits. Folders always get the
+    /// default permissions.
+    pub permission_mask: u32,
+}
+
+impl Default for ArchiveLimits {
+    fn default() -> Self {
+        Self {
+            max_total_size: 1024 * 1024 * 1024,
+            max_entries: 1000,
+            max_compression_ratio: 100,
+            permission_mask: 0o644,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExtractProgress {
+    pub entries: usize,
+    pub total: usize,
+}
+
+/// What to do with a file of a chart when a different one already exists at its place.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ConflictResolution {
+    /// Replace the existing file, which other charts using it will then play.
+    Overwrite,
+    /// Keep the existing file, which the new chart will then play.
+    Skip,
+    /// Install a new `.srtb` under a numbered name, like `song (1).srtb`, to keep both
+    /// versions of the chart. Other files are kept like with [`Skip`](Self::Skip), as
+    /// charts refer to them by their original name.
+    KeepBoth,
+}
+
+/// How conflicting files are handled, set in the app config.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum ConflictPolicy {
+    Overwrite,
+    Skip,
+    KeepBoth,
+    /// Ask the user for every chart with conflicts.
+    #[default]
+    Ask,
+}
+
+impl ConflictPolicy {
+    /// The resolution applied without asking, if any.
+    pub fn resolution(self) -> Option<ConflictResolution> {
+        match self {
+            ConflictPolicy::Overwrite => Some(ConflictResolution::Overwrite),
+            ConflictPolicy::Skip => Some(ConflictResolution::Skip),
+            ConflictPolicy::KeepBoth => Some(ConflictResolution::KeepBoth),
+            ConflictPolicy::Ask => None,
+        }
+    }
+}
+
+/// What an installation did with files that were already in the customs folder.
+/// Paths are relative to the customs folder.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InstallReport {
+    /// Files that were already installed with the same contents.
+    pub unchanged: usize,
+    pub overwritten: Vec<PathBuf>,
+    pub skipped: Vec<PathBuf>,
+    /// Files installed under another name, with that name.
+    pub renamed: Vec<(PathBuf, PathBuf)>,
+}
+
+/// Installs the chart in `zip` into the customs folder `destination`.
+///
+/// The archive is refused as a whole if it doesn't fit within `limits`. Entries are
+/// then extracted into a staging folder inside `destination`, so that they end up on
+/// the same file system and can be moved into place with renames. Files that already
+/// exist with the same contents are left alone, and the ones that differ are handled
+/// according to `policy`, calling `ask` to decide when it is [`ConflictPolicy::Ask`].
+///
+/// The customs folder is only touched once everything was extracted and checked, and if
+/// moving a file fails, the ones already moved are taken back out and any file they
+/// replaced is restored. Cancelling leaves the customs folder as it was.
+///
+/// Extraction runs alongside other installations, but only one at a time compares its
+/// files with the customs folder and moves them in. Staging folders left behind by
+/// earlier runs that didn't get to clean up are removed first.
+pub async fn decompress_zip<F>(
+    zip: &str,
+    destination: String,
+    limits: &ArchiveLimits,
+    policy: ConflictPolicy,
+    ask: impl FnOnce(Vec<PathBuf>) -> F,
+    cancel: &CancelToken,
+    mut on_progress: impl FnMut(ExtractProgress),
+) -> Result<InstallReport, SpinexusError>
+where
+    F: Future<Output = Result<ConflictResolution, SpinexusError>>,
+{
+    let dest = Path::new(destination.as_str());
+    if destination.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "no customs folder is set").into());
+    }
+    if !dest.is_dir() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("the customs folder {destination} doesn't exist")).into());
+    }
+    let mut archive = ZipArchive::new(File::open(zip)?)?;
+    check_archive(&mut archive, limits)?;
+
+    Staging::remove_stale(dest);
+    let staging = Staging::create(dest)?;
+    let res = install_staged(&mut archive, &staging, dest, limits, policy, ask, cancel, &mut on_progress).await;
+    staging.remove();
+    res
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn install_staged<F>(
+    archive: &mut ZipArchive<File>,
+    staging: &Staging,
+    dest: &Path,
+    limits: &ArchiveLimits,
+    policy: ConflictPolicy,
+    ask: impl FnOnce(Vec<PathBuf>) -> F,
+    cancel: &CancelToken,
+    on_progress: &mut impl FnMut(ExtractProgress),
+) -> Result<InstallReport, SpinexusError>
+where
+    F: Future<Output = Result<ConflictResolution, SpinexusError>>,
+{
+    extract_entries(archive, &staging.files, limits, cancel, on_progress)?;
+    validate(&staging.files)?;
+
+    let _installing = tokio::select! {
+        biased;
+        _ = cancel.cancelled() => Err(SpinexusError::Cancelled),
+        guard = INSTALLING.lock() => Ok(guard),
+    }?;
+    let steps = plan(staging, dest)?;
+    let conflicts: Vec<PathBuf> = steps.iter()
+        .filter(|(_, step)| *step == Step::Conflict)
+        .map(|(relative, _)| relative.clone())
+        .collect();
+    let resolution = match policy.resolution() {
+        _ if conflicts.is_empty() => ConflictResolution::Skip,
+        Some(resolution) => resolution,
+        None => {
+            let resolution = tokio::select! {
+                biased;
+                _ = cancel.cancelled() => Err(SpinexusError::Cancelled),
+                resolution = ask(conflicts) => resolution,
+            }?;
+            let total = archive.len();
+            on_progress(ExtractProgress { entries: total, total });
+            resolution
+        }
+    };
+    Ok(install(staging, dest, &steps, resolution)?)
+}
+
+/// Checks the archive against `limits` using the sizes and types it declares.
+/// Extraction then makes sure the entries don't turn out bigger than declared.
+fn check_archive(archive: &mut ZipArchive<File>, limits: &ArchiveLimits) -> Result<(), SpinexusError> {
+    if archive.len() > limits.max_entries {
+        return Err(SpinexusError::InvalidArchive(format!("it has more than {} entries", limits.max_entries)));
+    }
+    let mut total_size = 0u64;
+    for i in 0..archive.len() {
+        let file = archive.by_index_raw(i)?;
+        check_entry(&file, limits)?;
+        total_size = total_size.saturating_add(file.size());
+        if total_size > limits.max_total_size {
+            return Err(SpinexusError::InvalidArchive(format!("it takes more than {} MiB once extracted", limits.max_total_size / (1024 * 1024))));
+        }
+    }
+    Ok(())
+}
+
+fn check_entry(file: &ZipFile, limits: &ArchiveLimits) -> Result<(), SpinexusError> {
+    let name = file.name();
+    if file.enclosed_name().is_none() {
+        return Err(SpinexusError::InvalidArchive(format!("{name} points outside of the customs folder")));
+    }
+    match file.unix_mode().map(|mode| mode & S_IFMT) {
+        None | Some(0) | Some(S_IFREG) | Some(S_IFDIR) => {}
+        Some(S_IFLNK) => return Err(SpinexusError::InvalidArchive(format!("{name} is a symbolic link"))),
+        Some(_) => return Err(SpinexusError::InvalidArchive(format!("{name} is neither a file nor a folder"))),
+    }
+    let ratio_exceeded = file.size() > file.compressed_size().saturating_mul(limits.max_compression_ratio);
+    if file.size() >= RATIO_CHECK_MIN_SIZE && ratio_exceeded {
+        return Err(SpinexusError::InvalidArchive(format!("{name} is compressed more than {} times", limits.max_compression_ratio)));
+    }
+    Ok(())
+}
+
+#[cfg_attr(not(unix), allow(unused_variables))]
+fn extract_entries(archive: &mut ZipArchive<File>, dest: &Path, limits: &ArchiveLimits, cancel: &CancelToken, on_progress: &mut impl FnMut(ExtractProgress)) -> Result<(), SpinexusError> {
+    let total = archive.len();
+    on_progress(ExtractProgress { entries: 0, total });
+
+    for i in 0..total {
+        cancel.check()?;
+        let mut file = archive.by_index(i)?;
+        let out_path = match file.enclosed_name() {
+            Some(path) => dest.join(path),
+            None => return Err(SpinexusError::InvalidArchive(format!("{} points outside of the customs folder", file.name()))),
+        };
+
+        if (*file.name()).ends_with('/') {
+            fs::create_dir_all(&out_path)?;
+        } else {
+            if let Some(p) = out_path.parent() {
+                fs::create_dir_all(p)?;
+            }
+            let mut out_file = File::create(&out_path)?;
+            // Reading one byte past the declared size is enough to tell that it lied.
+            let size = file.size();
+            let written = io::copy(&mut (&mut file).take(size + 1), &mut out_file)?;
+            if written != size {
+                return Err(SpinexusError::InvalidArchive(format!("{} isn't the size it claims to be", file.name())));
+            }
+
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+
+                if let Some(mode) = file.unix_mode() {
+                    fs::set_permissions(&out_path, fs::Permissions::from_mode(mode & limits.permission_mask))?;
+                }
+            }
+        }
+
+        on_progress(ExtractProgress { entries: i + 1, total });
+    }
+
+    Ok(())
+}
+
+/// Checks that what was extracted looks like a chart, i.e. has an `.srtb` at its root.
+fn validate(files: &Path) -> Result<(), SpinexusError> {
+    let has_chart = fs::read_dir(files)?
+        .flatten()
+        .any(|entry| entry.path().extension().is_some_and(|ext| ext == "srtb") && entry.path().is_file());
+    match has_chart {
+        true => Ok(()),
+        false => Err(SpinexusError::InvalidArchive("it doesn't contain a chart".into())),
+    }
+}
+
+/// Working folder for one installation, removed once it is over.
+struct Staging {
+    root: PathBuf,
+    /// Extracted entries, laid out as they will be in the customs folder.
+    files: PathBuf,
+    /// Files from the customs folder that are being replaced, kept until the
+    /// installation went through.
+    backup: PathBuf,
+}
+
+/// How old a staging folder of another process has to be to count as left behind, where
+/// it can't be told whether that process is still running.
+#[cfg(not(target_os = "linux"))]
+const STALE_STAGING_AGE: std::time::Duration = std::time::Duration::from_secs(24 * 60 * 60);
+
+impl Staging {
+    const PREFIX: &'static str = ".spinexus-staging-";
+
+    fn create(dest: &Path) -> io::Result<Self> {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+        let root = dest.join(format!("{}{}-{nanos}", Self::PREFIX, std::process::id()));
+        let staging = Self {
+            files: root.join("files"),
+            backup: root.join("backup"),
+            root,
+        };
+        fs::create_dir_all(&staging.files)?;
+        fs::create_dir_all(&staging.backup)?;
+        Ok(staging)
+    }
+
+    fn remove(self) {
+        // Leftovers are hidden and harmless, the installation result matters more.
+        let _ = fs::remove_dir_all(&self.root);
+    }
+
+    /// Removes the staging folders in `dest` of processes that are gone, which crashed or
+    /// were killed during an installation.
+    fn remove_stale(dest: &Path) {
+        let Ok(entries) = fs::read_dir(dest) else { return };
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let pid = name.to_str().and_then(|name| name.strip_prefix(Self::PREFIX)).and_then(|rest| rest.split('-').next());
+            let Some(pid) = pid.and_then(|pid| pid.parse::<u32>().ok()) else { continue };
+            if pid != std::process::id() && !is_running(pid, &entry.path()) {
+                let _ = fs::remove_dir_all(entry.path());
+            }
+        }
+    }
+}
+
+/// Whether the process `pid` that created the staging folder `path` may still be running.
+#[cfg(target_os = "linux")]
+fn is_running(pid: u32, _path: &Path) -> bool {
+    Path::new("/proc").join(pid.to_string()).exists()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn is_running(_pid: u32, path: &Path) -> bool {
+    let age = fs::metadata(path).and_then(|meta| meta.modified()).ok().and_then(|modified| modified.elapsed().ok());
+    age.is_none_or(|age| age < STALE_STAGING_AGE)
+}
+
+/// Everything an installation changed in the customs folder, to undo it on failure.
+#[derive(Default)]
+struct Journal {
+    created_dirs: Vec<PathBuf>,
+    installed: Vec<PathBuf>,
+    /// Replaced files, with where they were moved to.
+    replaced: Vec<(PathBuf, PathBuf)>,
+}
+
+impl Journal {
+    fn rollback(self) {
+        // Best effort: a step that can't be undone shouldn't stop the others.
+        for path in self.installed.iter().rev() {
+            let _ = fs::remove_file(path);
+        }
+        for (path, backup) in self.replaced.iter().rev() {
+            let _ = fs::rename(backup, path);
+        }
+        for dir in self.created_dirs.iter().rev() {
+            let _ = fs::remove_dir(dir);
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Step {
+    CreateDir,
+    Install,
+    /// The same file is already installed.
+    Unchanged,
+    /// A different file is already installed.
+    Conflict,
+}
+
+/// Decides what to do with every staged path, before anything is written.
+fn plan(staging: &Staging, dest: &Path) -> io::Result<Vec<(PathBuf, Step)>> {
+    let mut steps = vec![];
+    for relative in staged_paths(&staging.files)? {
+        let staged = staging.files.join(&relative);
+        let target = dest.join(&relative);
+        let step = if staged.is_dir() {
+            Step::CreateDir
+        } else if !target.is_file() {
+            Step::Install
+        } else if file_hash(&staged)? == file_hash(&target)? {
+            Step::Unchanged
+        } else {
+            Step::Conflict
+        };
+        steps.push((relative, step));
+    }
+    Ok(steps)
+}
+
+fn file_hash(path: &Path) -> io::Result<Vec<u8>> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().to_vec())
+}
+
+/// Moves the staged files into `dest`, rolling back everything if one can't be moved.
+fn install(staging: &Staging, dest: &Path, steps: &[(PathBuf, Step)], resolution: ConflictResolution) -> io::Result<InstallReport> {
+    let mut journal = Journal::default();
+    let mut report = InstallReport::default();
+    for (relative, step) in steps {
+        if let Err(e) = install_one(staging, dest, relative, step, resolution, &mut journal, &mut report) {
+            journal.rollback();
+            return Err(e);
+        }
+    }
+    Ok(report)
+}
+
+fn install_one(
+    staging: &Staging,
+    dest: &Path,
+    relative: &Path,
+    step: &Step,
+    resolution: ConflictResolution,
+    journal: &mut Journal,
+    report: &mut InstallReport,
+) -> io::Result<()> {
+    let staged = staging.files.join(relative);
+    let mut target = dest.join(relative);
+    match (step, resolution) {
+        (Step::CreateDir, _) => return create_dir_all_tracked(&target, &mut journal.created_dirs),
+        (Step::Install, _) => {}
+        (Step::Unchanged, _) => {
+            report.unchanged += 1;
+            return Ok(());
+        }
+        (Step::Conflict, ConflictResolution::KeepBoth) if is_chart_file(relative) => {
+            target = numbered_path(&target);
+            let renamed = target.strip_prefix(dest).unwrap_or(&target).to_path_buf();
+            report.renamed.push((relative.to_path_buf(), renamed));
+        }
+        (Step::Conflict, ConflictResolution::Skip | ConflictResolution::KeepBoth) => {
+            report.skipped.push(relative.to_path_buf());
+            return Ok(());
+        }
+        (Step::Conflict, ConflictResolution::Overwrite) => {
+            let backup = staging.backup.join(relative);
+            if let Some(parent) = backup.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::rename(&target, &backup)?;
+            journal.replaced.push((target.clone(), backup));
+            report.overwritten.push(relative.to_path_buf());
+        }
+    }
+    if let Some(parent) = target.parent() {
+        create_dir_all_tracked(parent, &mut journal.created_dirs)?;
+    }
+    if target.exists() {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} is in the way", target.display())));
+    }
+    fs::rename(&staged, &target)?;
+    journal.installed.push(target);
+    Ok(())
+}
+
+/// Whether `path` is a chart's `.srtb`, the only kind of file kept twice under
+/// [`ConflictResolution::KeepBoth`].
+fn is_chart_file(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "srtb")
+}
+
+/// Returns `path` with the first number that makes it free added to its name.
+fn numbered_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path.extension().map(|ext| format!(".{}", ext.to_string_lossy())).unwrap_or_default();
+    (1..)
+        .map(|n| path.with_file_name(format!("{stem} ({n}){extension}")))
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+/// Lists everything under `root` relative to it, sorted so that folders come before
+/// their contents.
+fn staged_paths(root: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut paths = vec![];
+    let mut pending = vec![PathBuf::new()];
+    while let Some(dir) = pending.pop() {
+        for entry in fs::read_dir(root.join(&dir))? {
+            let entry = entry?;
+            let relative = dir.join(entry.file_name());
+            if entry.file_type()?.is_dir() {
+                pending.push(relative.clone());
+            }
+            paths.push(relative);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Like `fs::create_dir_all`, recording every folder that didn't exist yet.
+fn create_dir_all_tracked(dir: &Path, created: &mut Vec<PathBuf>) -> io::Result<()> {
+    let mut missing: Vec<_> = dir.ancestors().take_while(|p| !p.exists()).map(Path::to_path_buf).collect();
+    fs::create_dir_all(dir)?;
+    missing.reverse();
+    created.extend(missing);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use crate::test_server::TempDir;
+
+    fn write_zip(path: &Path, entries: &[(&str, &str)]) {
+        let mut zip = zip::ZipWriter::new(File::create(path).unwrap());
+        for (name, contents) in entries {
+            zip.start_file(*name, zip::write::FileOptions::default()).unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    /// Every path under `dir` with the contents of files.
+    fn snapshot(dir: &Path) -> Vec<(PathBuf, Option<String>)> {
+        staged_paths(dir).unwrap()
+            .into_iter()
+            .map(|path| {
+                let contents = fs::read_to_string(dir.join(&path)).ok();
+                (path, contents)
+            })
+            .collect()
+    }
+
+    /// Installs `dir/chart.zip` into `dir/customs`, cancelling once `cancel_after`
+    /// entries were extracted. Conflicts must be resolved by `policy`.
+    async fn install_chart_zip(dir: &Path, limits: &ArchiveLimits, policy: ConflictPolicy, cancel_after: Option<usize>) -> Result<InstallReport, SpinexusError> {
+        let zip = dir.join("chart.zip");
+        let customs = dir.join("customs");
+        fs::create_dir_all(&customs).unwrap();
+        let cancel = CancelToken::new();
+        let ask = |conflicts| async move { panic!("asked about {conflicts:?}") };
+        decompress_zip(zip.to_str().unwrap(), customs.to_str().unwrap().into(), limits, policy, ask, &cancel, |p| {
+            if Some(p.entries) == cancel_after {
+                cancel.cancel();
+            }
+        }).await
+    }
+
+    async fn install_zip(dir: &Path, entries: &[(&str, &str)], cancel_after: Option<usize>) -> Result<InstallReport, SpinexusError> {
+        write_zip(&dir.join("chart.zip"), entries);
+        install_chart_zip(dir, &ArchiveLimits::default(), ConflictPolicy::Overwrite, cancel_after).await
+    }
+
+    async fn assert_rejected(dir: &Path, limits: &ArchiveLimits) {
+        let customs = customs_with_other_chart(dir);
+        let before = snapshot(&customs);
+        let res = install_chart_zip(dir, limits, ConflictPolicy::Overwrite, None).await;
+
+        assert!(matches!(res, Err(SpinexusError::InvalidArchive(_))), "{res:?}");
+        assert_eq!(snapshot(&customs), before);
+    }
+
+    fn customs_with_other_chart(dir: &Path) -> PathBuf {
+        let customs = dir.join("customs");
+        fs::create_dir_all(customs.join("AudioClips")).unwrap();
+        fs::write(customs.join("AudioClips/other.ogg"), "other").unwrap();
+        fs::write(customs.join("chart.srtb"), "old chart").unwrap();
+        customs
+    }
+
+    #[tokio::test]
+    async fn installs_chart() {
+        let dir = TempDir::new();
+        let customs = customs_with_other_chart(&dir);
+        install_zip(&dir, &[("chart.srtb", "new chart"), ("AudioClips/chart.ogg", "audio")], None).await.unwrap();
+
+        assert_eq!(snapshot(&customs), [
+            (PathBuf::from("AudioClips"), None),
+            (PathBuf::from("AudioClips/chart.ogg"), Some("audio".into())),
+            (PathBuf::from("AudioClips/other.ogg"), Some("other".into())),
+            (PathBuf::from("chart.srtb"), Some("new chart".into())),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn cancelling_leaves_customs_untouched() {
+        let dir = TempDir::new();
+        let customs = customs_with_other_chart(&dir);
+        let before = snapshot(&customs);
+        let res = install_zip(&dir, &[("chart.srtb", "new chart"), ("AudioClips/chart.ogg", "audio"), ("AlbumArt/chart.png", "cover")], Some(2)).await;
+
+        assert!(matches!(res, Err(SpinexusError::Cancelled)));
+        assert_eq!(snapshot(&customs), before);
+    }
+
+    #[tokio::test]
+    async fn rejects_archive_without_chart() {
+        let dir = TempDir::new();
+        let customs = customs_with_other_chart(&dir);
+        let before = snapshot(&customs);
+        let res = install_zip(&dir, &[("AudioClips/chart.ogg", "audio")], None).await;
+
+        assert!(matches!(res, Err(SpinexusError::InvalidArchive(_))));
+        assert_eq!(snapshot(&customs), before);
+    }
+
+    #[tokio::test]
+    async fn rolls_back_when_a_file_cant_be_moved() {
+        let dir = TempDir::new();
+        let customs = customs_with_other_chart(&dir);
+        // A folder can't be replaced by a file. It is installed after `chart.srtb` was
+        // replaced and `AlbumArt` created, so both have to be undone.
+        fs::create_dir_all(customs.join("zz/blocker")).unwrap();
+        let before = snapshot(&customs);
+        let res = install_zip(&dir, &[
+            ("chart.srtb", "new chart"),
+            ("AlbumArt/chart.png", "cover"),
+            ("zz/blocker", "file"),
+        ], None).await;
+
+        assert!(matches!(res, Err(SpinexusError::Io(_))));
+        assert_eq!(snapshot(&customs), before);
+    }
+
+    #[tokio::test]
+    async fn rejects_archives_beyond_limits() {
+        let dir = TempDir::new();
+        write_zip(&dir.join("chart.zip"), &[("chart.srtb", "new chart"), ("AudioClips/chart.ogg", "audio")]);
+
+        assert_rejected(&dir, &ArchiveLimits { max_entries: 1, ..ArchiveLimits::default() }).await;
+        assert_rejected(&dir, &ArchiveLimits { max_total_size: 10, ..ArchiveLimits::default() }).await;
+    }
+
+    #[tokio::test]
+    async fn rejects_highly_compressed_entries() {
+        let dir = TempDir::new();
+        let mut zip = zip::ZipWriter::new(File::create(dir.join("chart.zip")).unwrap());
+        zip.start_file("chart.srtb", zip::write::FileOptions::default()).unwrap();
+        zip.write_all(&vec![0; 2 * RATIO_CHECK_MIN_SIZE as usize]).unwrap();
+        zip.finish().unwrap();
+
+        assert_rejected(&dir, &ArchiveLimits::default()).await;
+    }
+
+    #[tokio::test]
+    async fn rejects_symlinks() {
+        let dir = TempDir::new();
+        let mut zip = zip::ZipWriter::new(File::create(dir.join("chart.zip")).unwrap());
+        zip.start_file("chart.srtb", zip::write::FileOptions::default()).unwrap();
+        zip.write_all(b"new chart").unwrap();
+        zip.add_symlink("AudioClips/chart.ogg", "/etc/passwd", zip::write::FileOptions::default()).unwrap();
+        zip.finish().unwrap();
+
+        assert_rejected(&dir, &ArchiveLimits::default()).await;
+    }
+
+    #[tokio::test]
+    async fn rejects_paths_outside_customs() {
+        let dir = TempDir::new();
+        write_zip(&dir.join("chart.zip"), &[("chart.srtb", "new chart"), ("../evil.sh", "evil")]);
+
+        assert_rejected(&dir, &ArchiveLimits::default()).await;
+        assert!(!dir.join("evil.sh").exists());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn masks_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new();
+        let customs = customs_with_other_chart(&dir);
+        let mut zip = zip::ZipWriter::new(File::create(dir.join("chart.zip")).unwrap());
+        zip.start_file("chart.srtb", zip::write::FileOptions::default().unix_permissions(0o777)).unwrap();
+        zip.write_all(b"new chart").unwrap();
+        zip.finish().unwrap();
+        install_chart_zip(&dir, &ArchiveLimits::default(), ConflictPolicy::Overwrite, None).await.unwrap();
+
+        let mode = fs::metadata(customs.join("chart.srtb")).unwrap().permissions().mode();
+        assert_eq!(mode & 0o7777, 0o644);
+    }
+
+    /// The other chart's files, with different contents.
+    const CONFLICTING: [(&str, &str); 2] = [("chart.srtb", "new chart"), ("AudioClips/other.ogg", "changed")];
+
+    #[tokio::test]
+    async fn skips_identical_files() {
+        let dir = TempDir::new();
+        let customs = customs_with_other_chart(&dir);
+        let before = snapshot(&customs);
+        write_zip(&dir.join("chart.zip"), &[("chart.srtb", "old chart"), ("AudioClips/other.ogg", "other")]);
+        let report = install_chart_zip(&dir, &ArchiveLimits::default(), ConflictPolicy::Ask, None).await.unwrap();
+
+        assert_eq!(report, InstallReport { unchanged: 2, ..InstallReport::default() });
+        assert_eq!(snapshot(&customs), before);
+    }
+
+    #[tokio::test]
+    async fn skips_conflicting_files() {
+        let dir = TempDir::new();
+        let customs = customs_with_other_chart(&dir);
+        let before = snapshot(&customs);
+        write_zip(&dir.join("chart.zip"), &CONFLICTING);
+        let report = install_chart_zip(&dir, &ArchiveLimits::default(), ConflictPolicy::Skip, None).await.unwrap();
+
+        assert_eq!(report.skipped, [PathBuf::from("AudioClips/other.ogg"), PathBuf::from("chart.srtb")]);
+        assert_eq!(snapshot(&customs), before);
+    }
+
+    #[tokio::test]
+    async fn refuses_missing_customs_folder() {
+        let dir = TempDir::new();
+        write_zip(&dir.join("chart.zip"), &[("chart.srtb", "chart")]);
+        let zip = dir.file("chart.zip");
+        let ask = |conflicts| async move { panic!("asked about {conflicts:?}") };
+        for destination in [String::new(), dir.file("customs")] {
+            let res = decompress_zip(&zip, destination, &ArchiveLimits::default(), ConflictPolicy::Ask, ask, &CancelToken::new(), |_| {}).await;
+            assert!(matches!(res, Err(SpinexusError::Io(_))), "{res:?}");
+        }
+        assert!(!dir.join("customs").exists());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn removes_stale_staging_folders() {
+        let dir = TempDir::new();
+        let customs = customs_with_other_chart(&dir);
+        // No process can have the largest pid, while this one is still installing.
+        let stale = customs.join(format!(".spinexus-staging-{}-1", u32::MAX));
+        let ours = customs.join(format!(".spinexus-staging-{}-1", std::process::id()));
+        for staging in [&stale, &ours] {
+            fs::create_dir_all(staging.join("files/AudioClips")).unwrap();
+            fs::write(staging.join("files/AudioClips/song.ogg"), "audio").unwrap();
+        }
+        install_zip(&dir, &[("chart.srtb", "old chart")], None).await.unwrap();
+
+        assert!(!stale.exists());
+        assert!(ours.exists());
+    }
+
+    #[tokio::test]
+    async fn keeps_both_files() {
+        let dir = TempDir::new();
+        let customs = customs_with_other_chart(&dir);
+        write_zip(&dir.join("chart.zip"), &CONFLICTING);
+        let report = install_chart_zip(&dir, &ArchiveLimits::default(), ConflictPolicy::KeepBoth, None).await.unwrap();
+
+        // The new chart plays the installed audio, as it still refers to it by name.
+        assert_eq!(report.renamed, [(PathBuf::from("chart.srtb"), PathBuf::from("chart (1).srtb"))]);
+        assert_eq!(report.skipped, [PathBuf::from("AudioClips/other.ogg")]);
+        assert_eq!(snapshot(&customs), [
+            (PathBuf::from("AudioClips"), None),
+            (PathBuf::from("AudioClips/other.ogg"), Some("other".into())),
+            (PathBuf::from("chart (1).srtb"), Some("new chart".into())),
+            (PathBuf::from("chart.srtb"), Some("old chart".into())),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn asks_before_writing() {
+        let dir = TempDir::new();
+        let customs = customs_with_other_chart(&dir);
+        let before = snapshot(&customs);
+        write_zip(&dir.join("chart.zip"), &CONFLICTING);
+        let ask = |conflicts: Vec<PathBuf>| {
+            assert_eq!(conflicts, [PathBuf::from("AudioClips/other.ogg"), PathBuf::from("chart.srtb")]);
+            // Only the staging folder was added so far.
+            let mut now = snapshot(&customs);
+            now.retain(|(path, _)| !path.to_string_lossy().starts_with(".spinexus-staging"));
+            assert_eq!(now, before);
+            async { Ok(ConflictResolution::Overwrite) }
+        };
+        let zip = dir.join("chart.zip");
+        let report = decompress_zip(zip.to_str().unwrap(), customs.to_str().unwrap().into(), &ArchiveLimits::default(), ConflictPolicy::Ask, ask, &CancelToken::new(), |_| {}).await.unwrap();
+
+        assert_eq!(report.overwritten.len(), 2);
+        assert_eq!(fs::read_to_string(customs.join("AudioClips/other.ogg")).unwrap(), "changed");
+    }
+
+    #[tokio::test]
+    async fn cancelling_while_asking_leaves_customs_untouched() {
+        let dir = TempDir::new();
+        let customs = customs_with_other_chart(&dir);
+        let before = snapshot(&customs);
+        write_zip(&dir.join("chart.zip"), &CONFLICTING);
+        let cancel = CancelToken::new();
+        let ask = |_| {
+            cancel.cancel();
+            std::future::pending()
+        };
+        let zip = dir.join("chart.zip");
+        let res = decompress_zip(zip.to_str().unwrap(), customs.to_str().unwrap().into(), &ArchiveLimits::default(), ConflictPolicy::Ask, ask, &cancel, |_| {}).await;
+
+        assert!(matches!(res, Err(SpinexusError::Cancelled)));
+        assert_eq!(snapshot(&customs), before);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn installs_take_turns() {
+        let dir = TempDir::new();
+        let customs = customs_with_other_chart(&dir);
+        let first = dir.file("first.zip");
+        write_zip(Path::new(&first), &[("first.srtb", "first"), ("AudioClips/other.ogg", "changed"), ("AudioClips/shared.ogg", "shared")]);
+        let second = dir.file("second.zip");
+        write_zip(Path::new(&second), &[("second.srtb", "second"), ("AudioClips/shared.ogg", "shared")]);
+
+        // The first installation is asked about `other.ogg` and takes its time to answer,
+        // while the second one starts and would plan to add `shared.ogg` as well.
+        let (asking, asked) = tokio::sync::oneshot::channel();
+        let destination = customs.to_str().unwrap().to_string();
+        let first = tokio::spawn(async move {
+            let ask = |_| async move {
+                asking.send(()).unwrap();
+                tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+                Ok(ConflictResolution::Overwrite)
+            };
+            decompress_zip(&first, destination, &ArchiveLimits::default(), ConflictPolicy::Ask, ask, &CancelToken::new(), |_| {}).await
+        });
+        asked.await.unwrap();
+        let ask = |conflicts| async move { panic!("asked about {conflicts:?}") };
+        let second = decompress_zip(&second, customs.to_str().unwrap().into(), &ArchiveLimits::default(), ConflictPolicy::Ask, ask, &CancelToken::new(), |_| {}).await;
+
+        assert_eq!(first.await.unwrap().unwrap().overwritten, [PathBuf::from("AudioClips/other.ogg")]);
+        assert_eq!(second.unwrap().unchanged, 1);
+    }
+}
@@ -1,36 +1,83 @@
 #![allow(non_snake_case)]
 
+mod api;
 mod app_config;
+mod cache;
 mod components;
+mod connectivity;
+mod deep_link;
 mod download;
+mod download_manager;
+mod error;
+mod install;
+mod memo;
 mod models;
+mod retry;
 mod route;
+#[cfg(test)]
+mod test_server;
+
+use std::cell::Cell;
 
 use dioxus::prelude::*;
 use dioxus_desktop::{Config, LogicalSize, WindowBuilder};
 use dioxus_router::prelude::*;
 
+use api::SpinShareClient;
 use app_config::AppConfig;
+use deep_link::{DeepLink, IncomingLinks};
+use download_manager::DownloadManager;
+use route::Route;
+
+struct AppProps {
+    initial_route: Route,
+    links: Cell<Option<tokio::sync::mpsc::UnboundedReceiver<Option<DeepLink>>>>,
+}
 
-fn App(cx: Scope) -> Element {
+fn App(cx: Scope<AppProps>) -> Element {
     use_shared_state_provider(cx, || AppConfig::load().unwrap_or_default());
+    let config = use_shared_state::<AppConfig>(cx).unwrap();
+    use_shared_state_provider(cx, || SpinShareClient::from_config(&config.read()));
+    let client = use_shared_state::<SpinShareClient>(cx).unwrap();
+    use_shared_state_provider(cx, || DownloadManager::from_config(client.read().clone(), &config.read()));
+    use_shared_state_provider(cx, || IncomingLinks(cx.props.links.take()));
+    let initial_route = cx.props.initial_route.clone();
     render! {
         style { include_str!("../tailwind.css") }
         div {
             class: "container mx-auto px-1 pt-2",
-            Router::<route::Route> {
-                config: || RouterConfig::default().history(MemoryHistory::default())
+            components::ConnectionBanner {}
+            components::ConflictDialog {}
+            Router::<Route> {
+                config: move || RouterConfig::default().history(MemoryHistory::with_initial_path(initial_route.clone()))
             }
         }
     }
 }
 
 fn main() {
+    // Started by opening a link, or launched again while already running.
+    let arg = std::env::args().nth(1);
+    if deep_link::forward_to_running_instance(arg.as_deref()) {
+        return;
+    }
+    let initial_route = arg.as_deref()
+        .and_then(DeepLink::parse)
+        .map(|link| link.route())
+        .unwrap_or(Route::Index {});
+    let links = deep_link::listen()
+        .map_err(|e| eprintln!("Warning: links opened while Spinexus is running won't reach it: {e}"))
+        .ok();
+
     let window = WindowBuilder::new()
         .with_title("Spinexus")
         .with_inner_size(LogicalSize::new(1280, 720))
         .with_min_inner_size(LogicalSize::new(800, 600));
     let config = Config::new()
         .with_window(window);
-    dioxus_desktop::launch_cfg(App, config);
+    let props = AppProps {
+        initial_route,
+        links: Cell::new(links),
+    };
+    dioxus_desktop::launch_with_props(App, props, config);
 }
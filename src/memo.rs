@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures_util::future::{BoxFuture, FutureExt, Shared};
+
+use crate::error::SpinexusError;
+
+type Body = Arc<Vec<u8>>;
+type SharedFetch = Shared<BoxFuture<'static, Result<Body, SpinexusError>>>;
+
+/// In-memory layer in front of the network and disk cache.
+///
+/// Identical requests made while one is already running wait for that one instead of
+/// going out again, and results are reused for the rest of the session as long as
+/// they are younger than the endpoint's TTL.
+#[derive(Default)]
+pub struct RequestMemo {
+    state: Mutex<MemoState>,
+}
+
+#[derive(Default)]
+struct MemoState {
+    in_flight: HashMap<String, SharedFetch>,
+    results: HashMap<String, (Instant, Body)>,
+}
+
+impl RequestMemo {
+    /// Returns the memoized body for `key` if it is younger than `ttl`, otherwise joins
+    /// the running request for `key` or starts `fetch`.
+    pub async fn get_or_fetch<F>(&self, key: String, ttl: Duration, force_refresh: bool, fetch: F) -> Result<Body, SpinexusError>
+    where
+        F: Future<Output = Result<Vec<u8>, SpinexusError>> + Send + 'static,
+    {
+        let shared = {
+            let mut state = self.state.lock().unwrap();
+            match state.results.get(&key) {
+                Some((fetched_at, body)) if !force_refresh && fetched_at.elapsed() < ttl => return Ok(body.clone()),
+                _ => {}
+            }
+            state.in_flight
+                .entry(key.clone())
+                .or_insert_with(|| fetch.map(|res| res.map(Arc::new)).boxed().shared())
+                .clone()
+        };
+
+        let res = shared.clone().await;
+
+        let mut state = self.state.lock().unwrap();
+        // A newer request for the same key may have started since, leave that one alone.
+        if state.in_flight.get(&key).is_some_and(|f| f.ptr_eq(&shared)) {
+            state.in_flight.remove(&key);
+        }
+        if let Ok(body) = &res {
+            state.results.insert(key, (Instant::now(), body.clone()));
+        }
+        res
+    }
+
+    pub fn clear(&self) {
+        self.state.lock().unwrap().results.clear();
+    }
+}
+
+impl fmt::Debug for RequestMemo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.state.lock().unwrap();
+        f.debug_struct("RequestMemo")
+            .field("in_flight", &state.in_flight.len())
+            .field("results", &state.results.len())
+            .finish()
+    }
+}
@@ -1,4 +1,7 @@
-use serde::{Serialize, Deserialize, de::DeserializeOwned};
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize, Deserializer};
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all="camelCase")]
@@ -12,6 +15,113 @@ pub struct FullChart {
     pub cover: String,
     pub paths: ChartPaths,
     pub file_reference: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default, deserialize_with = "deserialize_tags")]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub is_explicit: bool,
+    #[serde(flatten)]
+    pub difficulties: ChartDifficulties,
+    #[serde(default, with = "spin_date::option")]
+    pub upload_date: Option<DateTime<Utc>>,
+    #[serde(default, with = "spin_date::option")]
+    pub update_date: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub views: i32,
+    #[serde(default)]
+    pub downloads: i32,
+    #[serde(default)]
+    pub is_draft: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+    Expert,
+    XD,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 5] = [
+        Difficulty::Easy,
+        Difficulty::Normal,
+        Difficulty::Hard,
+        Difficulty::Expert,
+        Difficulty::XD,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Normal => "Normal",
+            Difficulty::Hard => "Hard",
+            Difficulty::Expert => "Expert",
+            Difficulty::XD => "XD",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChartDifficulty {
+    pub difficulty: Difficulty,
+    pub available: bool,
+    /// Difficulty rating set by the charter, if any.
+    pub rating: Option<i32>,
+}
+
+/// Difficulty availability and ratings, as flattened into chart objects by the API.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChartDifficulties {
+    #[serde(rename = "hasEasyDifficulty")]
+    pub has_easy: bool,
+    #[serde(rename = "hasNormalDifficulty")]
+    pub has_normal: bool,
+    #[serde(rename = "hasHardDifficulty")]
+    pub has_hard: bool,
+    #[serde(rename = "hasExtremeDifficulty")]
+    pub has_expert: bool,
+    #[serde(rename = "hasXDDifficulty")]
+    pub has_xd: bool,
+    #[serde(rename = "easyDifficulty")]
+    pub easy_rating: Option<i32>,
+    #[serde(rename = "normalDifficulty")]
+    pub normal_rating: Option<i32>,
+    #[serde(rename = "hardDifficulty")]
+    pub hard_rating: Option<i32>,
+    #[serde(rename = "expertDifficulty")]
+    pub expert_rating: Option<i32>,
+    #[serde(rename = "XDDifficulty")]
+    pub xd_rating: Option<i32>,
+}
+
+impl ChartDifficulties {
+    pub fn get(&self, difficulty: Difficulty) -> ChartDifficulty {
+        let (available, rating) = match difficulty {
+            Difficulty::Easy => (self.has_easy, self.easy_rating),
+            Difficulty::Normal => (self.has_normal, self.normal_rating),
+            Difficulty::Hard => (self.has_hard, self.hard_rating),
+            Difficulty::Expert => (self.has_expert, self.expert_rating),
+            Difficulty::XD => (self.has_xd, self.xd_rating),
+        };
+        ChartDifficulty {
+            difficulty,
+            available,
+            rating,
+        }
+    }
+
+    /// Returns the difficulties this chart can be played on, from easiest to hardest.
+    pub fn available(&self) -> Vec<ChartDifficulty> {
+        Difficulty::ALL
+            .iter()
+            .map(|d| self.get(*d))
+            .filter(|d| d.available)
+            .collect()
+    }
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
@@ -22,6 +132,7 @@ pub struct ChartPaths {
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all="camelCase")]
 pub struct PartialChart {
     pub id: i32,
     pub title: String,
@@ -29,65 +140,242 @@ pub struct PartialChart {
     pub artist: String,
     pub charter: String,
     pub cover: String,
-    // todo: implement more on the way
+    #[serde(default)]
+    pub file_reference: String,
+    #[serde(default)]
+    pub is_explicit: bool,
+    #[serde(flatten)]
+    pub difficulties: ChartDifficulties,
+    #[serde(default, with = "spin_date::option")]
+    pub update_date: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all="camelCase")]
 pub struct User {
     pub id: i32,
     pub username: String,
     pub avatar: String,
-    // todo: implement more on the way
+    #[serde(default)]
+    pub about: Option<String>,
+    #[serde(default)]
+    pub pronouns: Option<String>,
+    #[serde(default)]
+    pub is_verified: bool,
+    #[serde(default)]
+    pub is_patreon: bool,
+    #[serde(default)]
+    pub badges: Vec<UserBadge>,
+    #[serde(default)]
+    pub cards: Vec<UserCard>,
+    #[serde(default)]
+    pub upload_count: i32,
+    #[serde(default)]
+    pub playlist_count: i32,
+    #[serde(default)]
+    pub review_count: i32,
 }
 
-#[derive(Serialize, Deserialize)]
-struct SpinRequest<T> {
-    version: i32,
-    status: i32,
-    data: T,
+/// Site-wide badge shown next to a username, e.g. "Staff".
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct UserBadge {
+    pub title: String,
+    #[serde(default)]
+    pub description: String,
+    pub icon: String,
 }
 
-async fn request_data<T: DeserializeOwned>(endpoint: String) -> Result<T, reqwest::Error> {
-    let res = reqwest::get(endpoint)
-        .await?
-        .error_for_status()?
-        .json::<SpinRequest<T>>()
-        .await?;
-    Ok(res.data)
+/// Achievement card awarded to a user, e.g. for taking part in an event.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all="camelCase")]
+pub struct UserCard {
+    pub id: i32,
+    pub title: String,
+    #[serde(default)]
+    pub description: String,
+    pub icon: String,
+    #[serde(default, with = "spin_date::option")]
+    pub given_date: Option<DateTime<Utc>>,
 }
 
-pub async fn get_chart(id: i32) -> Result<FullChart, reqwest::Error> {
-    request_data(format!("https://spinsha.re/api/song/{}", id)).await
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all="camelCase")]
+pub struct Review {
+    pub id: i32,
+    /// ID of the user who wrote the review.
+    pub user: i32,
+    pub recommended: bool,
+    #[serde(default)]
+    pub comment: String,
+    #[serde(default, with = "spin_date::option")]
+    pub review_date: Option<DateTime<Utc>>,
 }
 
-pub async fn get_new_charts(page: i32) -> Result<Vec<PartialChart>, reqwest::Error> {
-    request_data(format!("https://spinsha.re/api/songs/new/{}", page)).await
+/// Share of reviews that recommend the chart, from 0 to 1. `None` if there are no reviews.
+pub fn recommendation_ratio(reviews: &[Review]) -> Option<f32> {
+    if reviews.is_empty() {
+        return None;
+    }
+    let recommended = reviews.iter().filter(|r| r.recommended).count();
+    Some(recommended as f32 / reviews.len() as f32)
 }
 
-pub async fn get_updated_charts(page: i32) -> Result<Vec<PartialChart>, reqwest::Error> {
-    request_data(format!("https://spinsha.re/api/songs/updated/{}", page)).await
+/// Gameplay video of a chart linked on SpinShare.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all="camelCase")]
+pub struct SpinPlay {
+    pub id: i32,
+    /// ID of the user who submitted the video.
+    pub user: i32,
+    pub video_url: String,
+    #[serde(default, with = "spin_date::option")]
+    pub submit_date: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Playlist {
+    pub id: i32,
+    pub title: String,
+    #[serde(default)]
+    pub description: String,
+    /// ID of the user who made the playlist.
+    pub user: i32,
+    #[serde(default)]
+    pub cover: String,
+    #[serde(default)]
+    pub songs: Vec<PartialChart>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct PartialPlaylist {
+    pub id: i32,
+    pub title: String,
+    #[serde(default)]
+    pub cover: String,
 }
 
-pub async fn get_weekly_hot_charts(page: i32) -> Result<Vec<PartialChart>, reqwest::Error> {
-    request_data(format!("https://spinsha.re/api/songs/hotThisWeek/{}", page)).await
+/// Everything shown on the SpinShare front page.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all="camelCase")]
+pub struct FrontPage {
+    #[serde(default)]
+    pub promos: Vec<Promo>,
+    #[serde(default)]
+    pub staff_picks: Vec<PartialChart>,
+    #[serde(default)]
+    pub hot_this_week: Vec<PartialChart>,
+    #[serde(default)]
+    pub hot_this_month: Vec<PartialChart>,
+    #[serde(default)]
+    pub new_charts: Vec<PartialChart>,
 }
 
-pub async fn get_monthly_hot_charts(page: i32) -> Result<Vec<PartialChart>, reqwest::Error> {
-    request_data(format!("https://spinsha.re/api/songs/hotThisMonth/{}", page)).await
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all="camelCase")]
+pub struct Promo {
+    pub id: i32,
+    pub title: String,
+    #[serde(rename = "type", default)]
+    pub kind: String,
+    #[serde(default)]
+    pub text_color: String,
+    #[serde(default)]
+    pub color: String,
+    pub image: String,
+    pub button: PromoButton,
 }
 
-pub async fn get_user(id: i32) -> Result<User, reqwest::Error> {
-    request_data(format!("https://spinsha.re/api/user/{}", id)).await
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct PromoButton {
+    #[serde(rename = "type")]
+    pub kind: i32,
+    pub data: String,
 }
 
-pub async fn get_charts_for_user(id: i32) -> Result<Vec<PartialChart>, reqwest::Error> {
-    request_data(format!("https://spinsha.re/api/user/{}/charts", id)).await
+/// Where a promo button leads.
+#[derive(Debug, PartialEq)]
+pub enum PromoTarget {
+    Chart(i32),
+    Playlist(i32),
+    Search(String),
+    Url(String),
 }
 
-#[derive(Serialize)]
+impl PromoButton {
+    pub fn target(&self) -> Option<PromoTarget> {
+        match self.kind {
+            0 => self.data.parse().ok().map(PromoTarget::Chart),
+            1 => self.data.parse().ok().map(PromoTarget::Playlist),
+            2 => Some(PromoTarget::Search(self.data.clone())),
+            3 => Some(PromoTarget::Url(self.data.clone())),
+            _ => None,
+        }
+    }
+}
+
+/// Charts per page in SpinShare's listings.
+pub const PAGE_SIZE: usize = 12;
+
+/// Chart listings that SpinShare serves a page at a time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChartList {
+    New,
+    Updated,
+    HotThisWeek,
+    HotThisMonth,
+}
+
+impl ChartList {
+    pub fn path(&self) -> &'static str {
+        match self {
+            ChartList::New => "songs/new",
+            ChartList::Updated => "songs/updated",
+            ChartList::HotThisWeek => "songs/hotThisWeek",
+            ChartList::HotThisMonth => "songs/hotThisMonth",
+        }
+    }
+}
+
+/// One page of a listing, numbered from 0.
+#[derive(Debug, PartialEq)]
+pub struct Page<T> {
+    pub number: i32,
+    pub items: Vec<T>,
+    /// Whether nothing comes after this page.
+    pub is_last: bool,
+    /// Number of pages in the listing, when known.
+    pub page_count: Option<i32>,
+}
+
+impl<T> Page<T> {
+    /// Cuts page `number` out of a complete list, for endpoints that return
+    /// everything at once.
+    pub fn from_all(items: Vec<T>, number: i32) -> Self {
+        let page_count = items.len().div_ceil(PAGE_SIZE).max(1) as i32;
+        let items = items.into_iter().skip(number.max(0) as usize * PAGE_SIZE).take(PAGE_SIZE).collect();
+        Self {
+            number,
+            items,
+            is_last: number + 1 >= page_count,
+            page_count: Some(page_count),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct SearchResults {
+    #[serde(default)]
+    pub users: Vec<User>,
+    #[serde(default, rename = "songs")]
+    pub charts: Vec<PartialChart>,
+}
+
+/// Filters for chart searches, on top of the search query itself.
+///
+/// Formats to and parses from a URL query string so it can live in a route.
+#[derive(Debug, Clone, PartialEq, Serialize)]
 #[serde(rename_all = "camelCase")]
-struct SearchChartBody {
-    pub search_query: String,
+pub struct SearchFilters {
     pub diff_easy: bool,
     pub diff_normal: bool,
     pub diff_hard: bool,
@@ -99,10 +387,9 @@ struct SearchChartBody {
     pub show_explicit: bool,
 }
 
-impl Default for SearchChartBody {
+impl Default for SearchFilters {
     fn default() -> Self {
         Self {
-            search_query: "".into(),
             diff_easy: true,
             diff_normal: true,
             diff_hard: true,
@@ -115,18 +402,186 @@ impl Default for SearchChartBody {
     }
 }
 
-pub async fn search_chart(query: String) -> Result<Vec<PartialChart>, reqwest::Error> {
-    let client = reqwest::Client::default();
-    let body = SearchChartBody {
-        search_query: query,
-        ..Default::default()
+impl SearchFilters {
+    pub fn includes(&self, difficulty: Difficulty) -> bool {
+        match difficulty {
+            Difficulty::Easy => self.diff_easy,
+            Difficulty::Normal => self.diff_normal,
+            Difficulty::Hard => self.diff_hard,
+            Difficulty::Expert => self.diff_expert,
+            Difficulty::XD => self.diff_xd,
+        }
+    }
+
+    pub fn set_includes(&mut self, difficulty: Difficulty, included: bool) {
+        match difficulty {
+            Difficulty::Easy => self.diff_easy = included,
+            Difficulty::Normal => self.diff_normal = included,
+            Difficulty::Hard => self.diff_hard = included,
+            Difficulty::Expert => self.diff_expert = included,
+            Difficulty::XD => self.diff_xd = included,
+        }
+    }
+}
+
+impl fmt::Display for SearchFilters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for difficulty in Difficulty::ALL {
+            write!(f, "{}={}&", difficulty.name().to_lowercase(), self.includes(difficulty) as u8)?;
+        }
+        write!(f, "from={}&to={}&explicit={}", self.diff_rating_from, self.diff_rating_to, self.show_explicit as u8)
+    }
+}
+
+impl From<&str> for SearchFilters {
+    /// Parses filters formatted by `Display`. Missing or malformed values keep their default.
+    fn from(query: &str) -> Self {
+        let mut filters = Self::default();
+        for (key, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+            let flag = value == "1";
+            match key {
+                "from" => filters.diff_rating_from = value.parse().unwrap_or(filters.diff_rating_from),
+                "to" => filters.diff_rating_to = value.parse().unwrap_or(filters.diff_rating_to),
+                "explicit" => filters.show_explicit = flag,
+                key => {
+                    if let Some(difficulty) = Difficulty::ALL.into_iter().find(|d| d.name().to_lowercase() == key) {
+                        filters.set_includes(difficulty, flag);
+                    }
+                }
+            }
+        }
+        filters
+    }
+}
+
+/// SpinShare tags are stored as a single comma-separated string, but accept a list too.
+fn deserialize_tags<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum RawTags {
+        List(Vec<String>),
+        Text(String),
+    }
+
+    let tags = match Option::<RawTags>::deserialize(deserializer)? {
+        Some(RawTags::List(tags)) => tags,
+        Some(RawTags::Text(tags)) => tags.split(',').map(str::to_string).collect(),
+        None => vec![],
     };
-    let res = client.post("https://spinsha.re/api/searchCharts")
-        .json(&body)
-        .send()
-        .await?
-        .error_for_status()?
-        .json::<SpinRequest<Vec<PartialChart>>>()
-        .await?;
-    Ok(res.data)
+    Ok(tags
+        .into_iter()
+        .map(|tag| tag.trim().to_string())
+        .filter(|tag| !tag.is_empty())
+        .collect())
+}
+
+/// (De)serializes the PHP `DateTime` objects SpinShare sends, e.g.
+/// `{"date": "2021-03-04 12:34:56.000000", "timezone_type": 3, "timezone": "UTC"}`.
+/// Plain RFC 3339 strings are accepted as well, which is also what gets serialized.
+mod spin_date {
+    use chrono::{DateTime, FixedOffset, NaiveDateTime, Utc};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum RawDate {
+        Php { date: String, timezone: Option<String> },
+        Text(String),
+    }
+
+    fn parse(raw: RawDate) -> Option<DateTime<Utc>> {
+        match raw {
+            RawDate::Php { date, timezone } => {
+                let naive = NaiveDateTime::parse_from_str(&date, "%Y-%m-%d %H:%M:%S%.f").ok()?;
+                // Named zones other than UTC would need a timezone database, SpinShare only uses UTC.
+                let offset = timezone
+                    .and_then(|tz| tz.parse::<FixedOffset>().ok())
+                    .unwrap_or(FixedOffset::east_opt(0).unwrap());
+                naive
+                    .and_local_timezone(offset)
+                    .single()
+                    .map(|date| date.with_timezone(&Utc))
+            }
+            RawDate::Text(date) => DateTime::parse_from_rfc3339(&date)
+                .map(|date| date.with_timezone(&Utc))
+                .ok(),
+        }
+    }
+
+    pub fn serialize<S: Serializer>(date: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&date.to_rfc3339())
+    }
+
+    /// Optional dates, where a date that can't be read counts as missing rather than
+    /// making the whole object fail to load.
+    pub mod option {
+        use serde::de::IgnoredAny;
+
+        use super::*;
+
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum MaybeDate {
+            Date(RawDate),
+            Other(IgnoredAny),
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error> {
+            match MaybeDate::deserialize(deserializer)? {
+                MaybeDate::Date(raw) => Ok(parse(raw)),
+                MaybeDate::Other(_) => Ok(None),
+            }
+        }
+
+        pub fn serialize<S: Serializer>(date: &Option<DateTime<Utc>>, serializer: S) -> Result<S::Ok, S::Error> {
+            match date {
+                Some(date) => super::serialize(date, serializer),
+                None => serializer.serialize_none(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn chart(extra: &str) -> FullChart {
+        let json = format!(r#"{{
+            "id": 1, "title": "Title", "subtitle": "", "artist": "Artist", "charter": "Charter",
+            "uploader": 2, "cover": "cover.png", "fileReference": "spinshare_1",
+            "paths": {{"ogg": "a.ogg", "cover": "a.png", "zip": "a.zip"}}
+            {extra}
+        }}"#);
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn reads_php_and_rfc3339_dates() {
+        let date = Utc.with_ymd_and_hms(2021, 3, 4, 12, 34, 56).unwrap();
+        let php = chart(r#", "uploadDate": {"date": "2021-03-04 12:34:56.000000", "timezone_type": 3, "timezone": "UTC"}"#);
+        let text = chart(r#", "uploadDate": "2021-03-04T14:34:56+02:00""#);
+
+        assert_eq!(php.upload_date, Some(date));
+        assert_eq!(text.upload_date, Some(date));
+    }
+
+    #[test]
+    fn missing_or_broken_dates_are_none() {
+        assert_eq!(chart("").upload_date, None);
+        assert_eq!(chart(r#", "uploadDate": null"#).upload_date, None);
+        assert_eq!(chart(r#", "uploadDate": "yesterday""#).upload_date, None);
+        assert_eq!(chart(r#", "uploadDate": {"date": "not a date"}"#).upload_date, None);
+        assert_eq!(chart(r#", "updateDate": 12"#).update_date, None);
+    }
+
+    #[test]
+    fn reads_tags_as_text_or_list() {
+        assert_eq!(chart(r#", "tags": "rock, metal,,  jazz ""#).tags, ["rock", "metal", "jazz"]);
+        assert_eq!(chart(r#", "tags": ["rock", " metal", ""]"#).tags, ["rock", "metal"]);
+        assert_eq!(chart(r#", "tags": null"#).tags, Vec::<String>::new());
+        assert!(chart("").tags.is_empty());
+    }
 }
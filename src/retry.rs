@@ -0,0 +1,173 @@
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Response, StatusCode};
+use serde::{Serialize, Deserialize};
+
+/// How failed requests to SpinShare are retried.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt. 0 disables retrying.
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled for every following one.
+    pub base_delay_ms: u64,
+    /// Upper bound for a single delay. A `Retry-After` asking for longer than this
+    /// makes the request fail instead of blocking the app.
+    pub max_delay_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay_ms: 500,
+            max_delay_ms: 10_000,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns how long to wait before retry number `attempt` (starting at 0), or `None`
+    /// to give up.
+    ///
+    /// Without `retry_after` the delay backs off exponentially, with a random half of
+    /// it dropped so that clients failing together don't retry in lockstep.
+    pub fn delay_for(&self, attempt: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        if attempt >= self.max_retries {
+            return None;
+        }
+        let max_delay = Duration::from_millis(self.max_delay_ms);
+        if let Some(retry_after) = retry_after {
+            return (retry_after <= max_delay).then_some(retry_after);
+        }
+        let backoff = Duration::from_millis(self.base_delay_ms)
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(max_delay);
+        Some(backoff / 2 + backoff.mul_f64(random_fraction() / 2.0))
+    }
+}
+
+/// Whether a response is worth retrying: server errors and rate limiting.
+pub fn is_retryable_status(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+/// Reads the `Retry-After` header, given either in seconds or as an HTTP date.
+pub fn retry_after(res: &Response) -> Option<Duration> {
+    let value = res.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let wait = date.timestamp() - chrono::Utc::now().timestamp();
+    Some(Duration::from_secs(wait.max(0) as u64))
+}
+
+/// Client-side token bucket limiting how fast requests are sent to SpinShare.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateLimit {
+    /// Requests allowed per second on average.
+    pub requests_per_second: f64,
+    /// Requests that can be sent at once after being idle.
+    pub burst: u32,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self {
+            requests_per_second: 5.0,
+            burst: 10,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct RateLimiter {
+    rate: f64,
+    capacity: f64,
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(limit: &RateLimit) -> Self {
+        let capacity = limit.burst.max(1) as f64;
+        Self {
+            rate: limit.requests_per_second.max(0.01),
+            capacity,
+            bucket: Mutex::new(Bucket {
+                tokens: capacity,
+                refilled_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// Waits until a request may be sent.
+    pub async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut bucket = self.bucket.lock().unwrap();
+                let now = Instant::now();
+                let refill = now.duration_since(bucket.refilled_at).as_secs_f64() * self.rate;
+                bucket.tokens = (bucket.tokens + refill).min(self.capacity);
+                bucket.refilled_at = now;
+                if bucket.tokens >= 1.0 {
+                    bucket.tokens -= 1.0;
+                    return;
+                }
+                Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate)
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Cheap xorshift randomness for jitter, not suitable for anything else.
+fn random_fraction() -> f64 {
+    static STATE: AtomicU64 = AtomicU64::new(0);
+    let mut x = STATE.load(Ordering::Relaxed);
+    if x == 0 {
+        x = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as u64 | 1;
+    }
+    x ^= x << 13;
+    x ^= x >> 7;
+    x ^= x << 17;
+    STATE.store(x, Ordering::Relaxed);
+    (x >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_grows_and_is_capped() {
+        let policy = RetryPolicy {
+            max_retries: 10,
+            base_delay_ms: 100,
+            max_delay_ms: 1_000,
+        };
+        for attempt in 0..10 {
+            let full = Duration::from_millis((100u64 << attempt).min(1_000));
+            let delay = policy.delay_for(attempt, None).unwrap();
+            assert!(delay >= full / 2 && delay <= full, "attempt {attempt}: {delay:?}");
+        }
+        assert_eq!(policy.delay_for(10, None), None);
+    }
+
+    #[test]
+    fn retry_after_is_used_as_is_within_limits() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.delay_for(0, Some(Duration::from_secs(2))), Some(Duration::from_secs(2)));
+        assert_eq!(policy.delay_for(0, Some(Duration::from_secs(60))), None);
+    }
+}
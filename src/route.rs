@@ -1,14 +1,18 @@
 use dioxus::prelude::*;
 use dioxus_router::prelude::*;
 use directories::UserDirs;
+use dioxus_desktop::use_window;
 use rfd::FileDialog;
 
 use crate::app_config::AppConfig;
 use crate::components::*;
-use crate::models::{get_chart, get_user};
+use crate::deep_link::IncomingLinks;
+use crate::install::ConflictPolicy;
+use crate::models::{ChartList, SearchFilters};
 
 #[derive(Routable, PartialEq, Debug, Clone)]
 pub enum Route {
+    #[layout(DeepLinkListener)]
     #[route("/")]
     Index {},
     #[nest("/charts")]
@@ -20,51 +24,57 @@ pub enum Route {
         HotMonthCharts {},
         #[route("/week")]
         HotWeekCharts {},
-        #[route("/search/:query")]
-        SearchCharts { query: String },
+        #[route("/search/:query?:filters")]
+        SearchCharts { query: String, filters: SearchFilters },
     #[end_nest]
+    #[route("/search/:query")]
+    Search { query: String },
+    #[route("/chart/ref/:reference")]
+    ChartByReference { reference: String },
     #[route("/chart/:id")]
     Chart { id: i32 },
     #[route("/user/:id")]
     User { id: i32 },
+    #[route("/playlist/:id")]
+    Playlist { id: i32 },
+    #[route("/downloads")]
+    Downloads {},
     #[route("/settings")]
     AppSettings {},
     #[route("/:..route")]
     NotFound { route: Vec<String> },
 }
 
-fn Index(cx: Scope) -> Element {
-    let query = use_state(cx, || "".to_string());
+/// Wraps every page to open links forwarded by later launches of the app.
+fn DeepLinkListener(cx: Scope) -> Element {
     let navigator = use_navigator(cx);
-    if !query.is_empty() {
-        navigator.push(Route::SearchCharts { query: query.to_string() });
-    }
-    render! {
-        HeaderButtons {}
-        div {
-            form {
-                onsubmit: move |event| {
-                    let search_query = match event.values.get("search-query") {
-                        Some(q) => &q[0],
-                        None => return,
-                    };
-                    query.with_mut(|q| {
-                        *q = search_query.to_string()
-                    });
-                },
-                input {
-                    name: "search-query",
-                    r#type: "text",
-                }
-                input {
-                    r#type: "submit",
+    let window = use_window(cx);
+    let links = use_shared_state::<IncomingLinks>(cx).unwrap();
+    use_future(cx, (), |_| {
+        let receiver = links.write().0.take();
+        to_owned![navigator, window];
+        async move {
+            let Some(mut receiver) = receiver else {
+                return;
+            };
+            while let Some(link) = receiver.recv().await {
+                window.set_minimized(false);
+                window.set_focus();
+                if let Some(link) = link {
+                    navigator.push(link.route());
                 }
             }
         }
-        div {
-            class: "text-5xl mx-auto my-3",
-            "This is the index page!"
-        }
+    });
+    render! {
+        Outlet::<Route> {}
+    }
+}
+
+fn Index(cx: Scope) -> Element {
+    render! {
+        HeaderButtons {}
+        SearchForm { query: String::new(), filters: SearchFilters::default() }
         Link {
             class: "btn btn-blue m-1",
             to: Route::NewCharts {},
@@ -90,112 +100,70 @@ fn Index(cx: Scope) -> Element {
             to: Route::AppSettings {},
             "Application Settings"
         }
+        FrontPageDisplay {}
     }
 }
 
 fn NewCharts(cx: Scope) -> Element {
-    let mut page = use_state(cx, || 0);
     render! {
         HeaderButtons{}
         h1 {
-            "Newest charts - Page {page}"
-        }
-        if **page > 0 {
-            rsx! {
-                button { class: "btn btn-blue", onclick: move |_| page -= 1, "Previous Page" },
-            }
+            "Newest charts"
         }
-        if **page < 6 {
-            rsx! {
-                button { class: "btn btn-blue", onclick: move |_| page += 1, "Next Page" },
-            }
-        }
-        ChartListing { mode: ChartListingMode::New(**page) }
+        ChartListing { mode: ChartListingMode::List(ChartList::New) }
     }
 }
 
 fn UpdatedCharts(cx: Scope) -> Element {
-    let mut page = use_state(cx, || 0);
     render! {
         HeaderButtons {}
         h1 {
             "Last updated charts"
         }
-        if **page > 0 {
-            rsx! {
-                button { class: "btn btn-blue", onclick: move |_| page -= 1, "Previous Page" },
-            }
-        }
-        if **page < 6 {
-            rsx! {
-                button { class: "btn btn-blue", onclick: move |_| page += 1, "Next Page" },
-            }
-        }
-        ChartListing { mode: ChartListingMode::Updated(**page) }
+        ChartListing { mode: ChartListingMode::List(ChartList::Updated) }
     }
 }
 
 fn HotMonthCharts(cx: Scope) -> Element {
-    let mut page = use_state(cx, || 0);
     render! {
         HeaderButtons {}
         h1 {
             "Hot this month"
         }
-        if **page > 0 {
-            rsx! {
-                button { class: "btn btn-blue", onclick: move |_| page -= 1, "Previous Page" },
-            }
-        }
-        if **page < 6 {
-            rsx! {
-                button { class: "btn btn-blue", onclick: move |_| page += 1, "Next Page" },
-            }
-        }
-        ChartListing { mode: ChartListingMode::HotMonth(**page) }
+        ChartListing { mode: ChartListingMode::List(ChartList::HotThisMonth) }
     }
 }
 
 fn HotWeekCharts(cx: Scope) -> Element {
-    let mut page = use_state(cx, || 0);
     render! {
         HeaderButtons {}
         h1 {
             "Hot this week"
         }
-        if **page > 0 {
-            rsx! {
-                button { class: "btn btn-blue", onclick: move |_| page -= 1, "Previous Page" },
-            }
-        }
-        if **page < 6 {
-            rsx! {
-                button { class: "btn btn-blue", onclick: move |_| page += 1, "Next Page" },
-            }
-        }
-        ChartListing { mode: ChartListingMode::HotWeek(**page) }
+        ChartListing { mode: ChartListingMode::List(ChartList::HotThisWeek) }
     }
 }
 
 #[inline_props]
-fn SearchCharts(cx: Scope, query: String) -> Element {
-    let mut page = use_state(cx, || 0);
+fn SearchCharts(cx: Scope, query: String, filters: SearchFilters) -> Element {
     render! {
         HeaderButtons {},
+        SearchForm { query: query.clone(), filters: filters.clone() }
         h1 {
             "Search results for {query}"
         }
-        if **page > 0 {
-            rsx! {
-                button { class: "btn btn-blue", onclick: move |_| page -= 1, "Previous Page" },
-            }
-        }
-        if **page < 6 {
-            rsx! {
-                button { class: "btn btn-blue", onclick: move |_| page += 1, "Next Page" },
-            }
+        ChartListing { mode: ChartListingMode::SearchChart(query.to_string(), filters.clone()) }
+    }
+}
+
+#[inline_props]
+fn Search(cx: Scope, query: String) -> Element {
+    render! {
+        HeaderButtons {},
+        h1 {
+            "Search results for {query}"
         }
-        ChartListing { mode: ChartListingMode::SearchChart(query.to_string(), **page) }
+        SearchAllResults { query: query.clone() }
     }
 }
 
@@ -210,18 +178,53 @@ fn NotFound(cx: Scope, route: Vec<String>) -> Element {
 
 #[inline_props]
 fn Chart(cx: Scope, id: i32) -> Element {
-    let chart = use_future(cx, (), |_| get_chart(*id));
+    render! {
+        ChartPage { lookup: ChartLookup::Id(*id) }
+    }
+}
+
+#[inline_props]
+fn ChartByReference(cx: Scope, reference: String) -> Element {
+    render! {
+        ChartPage { lookup: ChartLookup::Reference(reference.clone()) }
+    }
+}
+
+#[derive(PartialEq, Clone, Debug)]
+enum ChartLookup {
+    Id(i32),
+    Reference(String),
+}
+
+#[inline_props]
+fn ChartPage(cx: Scope, lookup: ChartLookup) -> Element {
+    let client = use_spinshare(cx);
+    let refreshes = use_state(cx, || 0);
+    let chart = use_future(cx, (lookup, refreshes.get()), |(lookup, refreshes)| {
+        let client = if refreshes > 0 { client.refreshing() } else { client };
+        async move {
+            match lookup {
+                ChartLookup::Id(id) => client.get_chart(id).await,
+                ChartLookup::Reference(reference) => client.get_chart_by_reference(&reference).await,
+            }
+        }
+    });
     match chart.value() {
         Some(Ok(chart)) => {
             render! {
                 HeaderButtons {}
+                button {
+                    class: "btn btn-outline-blue m-1",
+                    onclick: move |_| refreshes.modify(|r| r + 1),
+                    "Refresh"
+                }
                 ChartFullDisplay { chart: chart }
             }
         }
         Some(Err(err)) => {
             render! {
                 HeaderButtons {}
-                "An error occurred while fetching chart: {err}"
+                ShowError { context: "An error occurred while fetching chart", error: err, onretry: move |_| chart.restart() }
             }
         }
         None => {
@@ -235,7 +238,8 @@ fn Chart(cx: Scope, id: i32) -> Element {
 
 #[inline_props]
 fn User(cx: Scope, id: i32) -> Element {
-    let user = use_future(cx, (), |_| get_user(*id));
+    let client = use_spinshare(cx);
+    let user = use_future(cx, (id,), |(id,)| async move { client.get_user(id).await });
     match user.value() {
         Some(Ok(user)) => {
             render! {
@@ -246,7 +250,33 @@ fn User(cx: Scope, id: i32) -> Element {
         Some(Err(err)) => {
             render! {
                 HeaderButtons {}
-                "An error occurred while fetching user: {err}"
+                ShowError { context: "An error occurred while fetching user", error: err, onretry: move |_| user.restart() }
+            }
+        }
+        None => {
+            render! {
+                HeaderButtons {}
+                ShowLoading {}
+            }
+        }
+    }
+}
+
+#[inline_props]
+fn Playlist(cx: Scope, id: i32) -> Element {
+    let client = use_spinshare(cx);
+    let playlist = use_future(cx, (id,), |(id,)| async move { client.get_playlist(id).await });
+    match playlist.value() {
+        Some(Ok(playlist)) => {
+            render! {
+                HeaderButtons {}
+                PlaylistFullDisplay { playlist: playlist }
+            }
+        }
+        Some(Err(err)) => {
+            render! {
+                HeaderButtons {}
+                ShowError { context: "An error occurred while fetching playlist", error: err, onretry: move |_| playlist.restart() }
             }
         }
         None => {
@@ -258,8 +288,33 @@ fn User(cx: Scope, id: i32) -> Element {
     }
 }
 
+fn Downloads(cx: Scope) -> Element {
+    render! {
+        HeaderButtons {}
+        h1 {
+            "Downloads"
+        }
+        DownloadList {}
+    }
+}
+
+/// Choices for files of a chart that are already installed differently, as shown in the
+/// settings.
+const CONFLICT_POLICIES: [(ConflictPolicy, &str); 4] = [
+    (ConflictPolicy::Ask, "Ask me"),
+    (ConflictPolicy::Overwrite, "Replace them"),
+    (ConflictPolicy::Skip, "Keep the installed ones"),
+    (ConflictPolicy::KeepBoth, "Keep both charts, with the installed versions of other files"),
+];
+
 fn AppSettings(cx: Scope) -> Element {
     let config = use_shared_state::<AppConfig>(cx).unwrap();
+    let client = use_spinshare(cx);
+    let downloads = use_downloads(cx);
+    let policy_manager = downloads.clone();
+    let max_concurrent_downloads = config.read().max_concurrent_downloads;
+    let conflict_policy = config.read().conflict_policy;
+    let cache_status = use_state(cx, String::new);
     let customs_path = &config.read().customs_path;
 
     render! {
@@ -283,5 +338,59 @@ fn AppSettings(cx: Scope) -> Element {
                 "Browse"
             }
         }
+        div {
+            label {
+                "Simultaneous downloads "
+                input {
+                    class: "border rounded w-16 px-1",
+                    r#type: "number",
+                    min: "1",
+                    max: "8",
+                    value: "{max_concurrent_downloads}",
+                    onchange: move |event| {
+                        if let Ok(max) = event.value.parse::<usize>() {
+                            let max = max.clamp(1, 8);
+                            downloads.set_max_concurrent(max);
+                            config.write().max_concurrent_downloads = max;
+                            let _ = config.write().save();
+                        }
+                    },
+                }
+            }
+        }
+        div {
+            label {
+                "When a chart has files already installed by another one "
+                select {
+                    class: "border rounded px-1",
+                    onchange: move |event| {
+                        let choice = event.value.parse::<usize>().ok().and_then(|i| CONFLICT_POLICIES.get(i));
+                        if let Some((policy, _)) = choice {
+                            policy_manager.set_conflict_policy(*policy);
+                            config.write().conflict_policy = *policy;
+                            let _ = config.write().save();
+                        }
+                    },
+                    for (i, (policy, label)) in CONFLICT_POLICIES.iter().enumerate() {
+                        option { value: "{i}", selected: *policy == conflict_policy, "{label}" }
+                    }
+                }
+            }
+        }
+        div {
+            button {
+                class: "btn btn-outline-blue m-1",
+                onclick: move |_| {
+                    cache_status.set(match client.clear_cache() {
+                        Ok(_) => "Cache cleared".into(),
+                        Err(e) => format!("Could not clear cache: {e}"),
+                    });
+                },
+                "Clear cached SpinShare data"
+            }
+            span {
+                "{cache_status}"
+            }
+        }
     }
 }